@@ -13,6 +13,14 @@ sam build
 
 echo "🚀 Deploying to AWS..."
 
-sam deploy
+# IMAGES_BUCKET_NAME enables S3 access; CREATE_IMAGES_BUCKET=true also creates the bucket
+PARAMS=()
+if [ -n "$IMAGES_BUCKET_NAME" ]; then
+    PARAMS+=(--parameter-overrides
+        "ImagesBucketName=$IMAGES_BUCKET_NAME"
+        "CreateImagesBucket=${CREATE_IMAGES_BUCKET:-false}")
+fi
+
+sam deploy "${PARAMS[@]}"
 
 echo "✅ Deployment completed!"
\ No newline at end of file
@@ -0,0 +1,292 @@
+use crate::config::AppConfig;
+use crate::models::BinaryCompressionResult;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+static RESULT_CACHE: OnceLock<ResultCache> = OnceLock::new();
+
+pub fn init_result_cache(config: &AppConfig) {
+    let cache = ResultCache {
+        memory: Mutex::new(MemoryCache {
+            entries: LruCache::unbounded(),
+            used_bytes: 0,
+            budget_bytes: config.result_cache.memory_bytes,
+        }),
+        disk: config.result_cache.disk_dir.as_ref().map(|dir| {
+            let dir = PathBuf::from(dir);
+            let budget_bytes = config.result_cache.disk_bytes;
+            let entries = scan_disk(&dir);
+            let mut index = DiskIndex {
+                used_bytes: entries.iter().map(|(_, size)| size).sum(),
+                entries,
+            };
+            for key in index.evict(budget_bytes) {
+                remove_disk_entry(&dir, &key);
+            }
+            DiskCache {
+                dir,
+                budget_bytes,
+                index: Mutex::new(index),
+            }
+        }),
+    };
+    let _ = RESULT_CACHE.set(cache);
+}
+
+pub fn result_cache() -> Option<&'static ResultCache> {
+    RESULT_CACHE.get()
+}
+
+struct MemoryCache {
+    entries: LruCache<String, BinaryCompressionResult>,
+    used_bytes: usize,
+    budget_bytes: usize,
+}
+
+// Size on disk (image + metadata) of every cached entry, in LRU order
+struct DiskIndex {
+    entries: LruCache<String, u64>,
+    used_bytes: u64,
+}
+
+impl DiskIndex {
+    fn evict(&mut self, budget_bytes: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.used_bytes > budget_bytes {
+            match self.entries.pop_lru() {
+                Some((key, size)) => {
+                    self.used_bytes -= size;
+                    evicted.push(key);
+                }
+                None => break,
+            }
+        }
+        evicted
+    }
+}
+
+struct DiskCache {
+    dir: PathBuf,
+    budget_bytes: u64,
+    index: Mutex<DiskIndex>,
+}
+
+// Metadata stored next to the encoded bytes in the disk cache
+#[derive(Serialize, Deserialize)]
+struct DiskEntry {
+    original_size: usize,
+    original_format: String,
+    output_format: String,
+    width: u32,
+    height: u32,
+    #[serde(default)]
+    lqip: Option<String>,
+}
+
+// Everything here is called from blocking threads (see `optimize_image_bytes`)
+pub struct ResultCache {
+    memory: Mutex<MemoryCache>,
+    disk: Option<DiskCache>,
+}
+
+impl ResultCache {
+    pub fn get(&self, key: &str, etag: &str) -> Option<BinaryCompressionResult> {
+        if let Some(result) = self.memory.lock().ok()?.entries.get(key) {
+            return Some(result.clone());
+        }
+
+        let result = self.read_disk(key, etag)?;
+        self.put_memory(key, &result);
+        Some(result)
+    }
+
+    pub fn put(&self, key: &str, result: &BinaryCompressionResult) {
+        self.put_memory(key, result);
+        if let Err(e) = self.write_disk(key, result) {
+            eprintln!("⚠️ Error escribiendo cache en disco: {}", e);
+        }
+    }
+
+    fn put_memory(&self, key: &str, result: &BinaryCompressionResult) {
+        let Ok(mut memory) = self.memory.lock() else {
+            return;
+        };
+        let size = memory_size(key, result);
+        if size > memory.budget_bytes {
+            return;
+        }
+
+        if let Some(previous) = memory.entries.put(key.to_string(), result.clone()) {
+            memory.used_bytes -= memory_size(key, &previous);
+        }
+        memory.used_bytes += size;
+
+        while memory.used_bytes > memory.budget_bytes {
+            match memory.entries.pop_lru() {
+                Some((old_key, old)) => memory.used_bytes -= memory_size(&old_key, &old),
+                None => break,
+            }
+        }
+    }
+
+    fn read_disk(&self, key: &str, etag: &str) -> Option<BinaryCompressionResult> {
+        let disk = self.disk.as_ref()?;
+        let (bin_path, meta_path) = disk_paths(&disk.dir, key);
+        let entry: DiskEntry = serde_json::from_slice(&fs::read(meta_path).ok()?).ok()?;
+        let optimized_bytes = fs::read(bin_path).ok()?;
+        if let Ok(mut index) = disk.index.lock() {
+            index.entries.promote(key);
+        }
+
+        Some(BinaryCompressionResult {
+            optimized_size: optimized_bytes.len(),
+            optimized_bytes,
+            original_size: entry.original_size,
+            original_format: entry.original_format,
+            output_format: entry.output_format,
+            width: entry.width,
+            height: entry.height,
+            etag: etag.to_string(),
+            last_modified: None,
+            lqip: entry.lqip,
+        })
+    }
+
+    fn write_disk(&self, key: &str, result: &BinaryCompressionResult) -> std::io::Result<()> {
+        let Some(disk) = &self.disk else {
+            return Ok(());
+        };
+        if result.optimized_bytes.len() as u64 > disk.budget_bytes {
+            return Ok(());
+        }
+        let (bin_path, meta_path) = disk_paths(&disk.dir, key);
+        if let Some(dir) = bin_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let entry = DiskEntry {
+            original_size: result.original_size,
+            original_format: result.original_format.clone(),
+            output_format: result.output_format.clone(),
+            width: result.width,
+            height: result.height,
+            lqip: result.lqip.clone(),
+        };
+
+        // Write the bytes first and the metadata last (atomically), so readers
+        // never see metadata without its image
+        let tmp_bin = bin_path.with_extension("bin.tmp");
+        fs::write(&tmp_bin, &result.optimized_bytes)?;
+        fs::rename(&tmp_bin, &bin_path)?;
+
+        let meta = serde_json::to_vec(&entry)?;
+        let tmp_meta = meta_path.with_extension("json.tmp");
+        fs::write(&tmp_meta, &meta)?;
+        fs::rename(&tmp_meta, &meta_path)?;
+
+        let size = (result.optimized_bytes.len() + meta.len()) as u64;
+        let evicted = match disk.index.lock() {
+            Ok(mut index) => {
+                if let Some(previous) = index.entries.put(key.to_string(), size) {
+                    index.used_bytes -= previous;
+                }
+                index.used_bytes += size;
+                index.evict(disk.budget_bytes)
+            }
+            Err(_) => Vec::new(),
+        };
+        for old_key in evicted {
+            remove_disk_entry(&disk.dir, &old_key);
+        }
+
+        Ok(())
+    }
+}
+
+fn memory_size(key: &str, result: &BinaryCompressionResult) -> usize {
+    key.len() + result.optimized_bytes.len() + result.lqip.as_ref().map_or(0, |lqip| lqip.len())
+}
+
+fn disk_paths(dir: &Path, key: &str) -> (PathBuf, PathBuf) {
+    let dir = dir.join(&key[..2]);
+    (
+        dir.join(format!("{}.bin", key)),
+        dir.join(format!("{}.json", key)),
+    )
+}
+
+fn remove_disk_entry(dir: &Path, key: &str) {
+    let (bin_path, meta_path) = disk_paths(dir, key);
+    // Metadata first, so a concurrent read misses instead of finding it without its image
+    let _ = fs::remove_file(meta_path);
+    let _ = fs::remove_file(bin_path);
+}
+
+// Rebuilds the index from what a previous run left, oldest files first. Only our own
+// layout is touched (`{2 hex}/{hex}.bin|.json`); leftovers of interrupted writes
+// (`{hex}.bin.tmp`/`.json.tmp`) are removed and anything else is left alone
+fn scan_disk(dir: &Path) -> LruCache<String, u64> {
+    let mut found: HashMap<String, (u64, SystemTime)> = HashMap::new();
+    let shards = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|shard| shard.file_type().is_ok_and(|t| t.is_dir()))
+        .filter_map(|shard| {
+            let name = shard.file_name().into_string().ok()?;
+            (name.len() == 2 && is_hex(&name)).then_some((name, shard.path()))
+        });
+
+    for (shard, shard_path) in shards {
+        for file in fs::read_dir(shard_path).into_iter().flatten().flatten() {
+            let Ok(name) = file.file_name().into_string() else {
+                continue;
+            };
+            let own_key = |stem: &str| is_hex(stem) && stem.starts_with(&shard);
+
+            if let Some(stem) = name
+                .strip_suffix(".bin.tmp")
+                .or_else(|| name.strip_suffix(".json.tmp"))
+            {
+                if own_key(stem) {
+                    let _ = fs::remove_file(file.path());
+                }
+                continue;
+            }
+
+            let Some(key) = name
+                .strip_suffix(".bin")
+                .or_else(|| name.strip_suffix(".json"))
+                .filter(|stem| own_key(stem))
+            else {
+                continue;
+            };
+            let Ok(meta) = file.metadata() else {
+                continue;
+            };
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            let entry = found
+                .entry(key.to_string())
+                .or_insert((0, SystemTime::UNIX_EPOCH));
+            entry.0 += meta.len();
+            entry.1 = entry.1.max(modified);
+        }
+    }
+
+    let mut found: Vec<_> = found.into_iter().collect();
+    found.sort_by_key(|(_, (_, modified))| *modified);
+    let mut entries = LruCache::unbounded();
+    for (key, (size, _)) in found {
+        entries.put(key, size);
+    }
+    entries
+}
+
+fn is_hex(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::env;
 
 #[derive(Debug, Clone)]
@@ -5,6 +6,12 @@ pub struct AppConfig {
     pub server: ServerConfig,
     pub compression: CompressionConfig,
     pub cors: CorsConfig,
+    pub sources: SourceConfig,
+    pub signing: SigningConfig,
+    pub s3: S3Config,
+    pub presets: PresetsConfig,
+    pub cache: CacheConfig,
+    pub result_cache: ResultCacheConfig,
 }
 
 #[derive(Debug, Clone)]
@@ -22,6 +29,9 @@ pub struct CompressionConfig {
     pub aggressive_quality: u8,
     #[allow(dead_code)]
     pub timeout_seconds: u64,
+    pub batch_concurrency: usize,
+    pub batch_max_files: usize,
+    pub max_url_dimension: u32, // Largest side GET /img produces, after dpr
 }
 
 #[derive(Debug, Clone)]
@@ -29,6 +39,80 @@ pub struct CorsConfig {
     pub allowed_origins: Vec<String>,
 }
 
+#[derive(Debug, Clone)]
+pub struct SigningConfig {
+    pub key: Option<String>, // When set, GET /img requires a valid signature
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    pub endpoint: Option<String>, // Custom S3-compatible endpoint (MinIO), path-style
+    pub region: String,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    pub allowed_buckets: Vec<String>, // `bucket` or `bucket/prefix`; empty rejects every s3:// URI
+    pub event_source_prefix: String,  // ObjectCreated events outside this prefix are ignored
+    pub event_dest_prefix: String,
+    pub event_variants: Vec<(String, String)>, // (name, path options like `w:320,t:fill`)
+}
+
+impl S3Config {
+    // Variants are written to the same bucket, so a destination the source prefix
+    // covers (or an empty source prefix) would re-trigger the function forever
+    pub fn validate_event_prefixes(&self) -> Result<(), String> {
+        let (source, dest) = (&self.event_source_prefix, &self.event_dest_prefix);
+        if source.is_empty() {
+            return Err("S3_SOURCE_PREFIX no puede estar vacio".to_string());
+        }
+        if dest.starts_with(source.as_str()) || source.starts_with(dest.as_str()) {
+            return Err(format!(
+                "S3_SOURCE_PREFIX ({}) y S3_DEST_PREFIX ({}) no pueden solaparse",
+                source, dest
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    pub default_control: Option<String>, // Cache-Control for binary responses without a route entry
+    pub routes: HashMap<String, String>, // route (`/img`, `/resize`, ...) -> Cache-Control
+}
+
+#[derive(Debug, Clone)]
+pub struct ResultCacheConfig {
+    pub memory_bytes: usize, // In-process LRU budget for encoded results, 0 disables it
+    pub disk_dir: Option<String>,
+    pub disk_bytes: u64, // Disk budget, least recently used entries are deleted past it
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PresetsConfig {
+    // name -> query-style options (`w`, `h`, `t`, `f`, `q`, ...), overridable per request
+    pub presets: HashMap<String, HashMap<String, String>>,
+}
+
+impl PresetsConfig {
+    // Loaded apart from `AppConfig::from_env` so the server reports a broken preset
+    // at startup and the `sign` CLI doesn't need them
+    pub fn from_env() -> Result<Self, String> {
+        Ok(Self {
+            presets: load_presets()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceConfig {
+    pub origin_url: Option<String>, // Base URL for GET /img/{options}/{source}
+    pub fetch_timeout_seconds: u64,
+    pub allowed_hosts: Vec<String>, // Hosts allowed for `url=`/`image_url`, empty = any public host
+    pub allow_private_networks: bool,
+    pub max_redirects: usize,
+}
+
 impl AppConfig {
     pub fn from_env() -> Self {
         dotenv::dotenv().ok();
@@ -62,6 +146,25 @@ impl AppConfig {
                     .unwrap_or_else(|_| "10".to_string())
                     .parse()
                     .unwrap_or(10),
+                batch_concurrency: env::var("BATCH_CONCURRENCY")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|v| *v > 0)
+                    .unwrap_or_else(|| {
+                        std::thread::available_parallelism()
+                            .map(|n| n.get())
+                            .unwrap_or(4)
+                    }),
+                batch_max_files: env::var("BATCH_MAX_FILES")
+                    .unwrap_or_else(|_| "50".to_string())
+                    .parse()
+                    .unwrap_or(50),
+                max_url_dimension: env::var("IMG_MAX_DIMENSION")
+                    .unwrap_or_else(|_| "4096".to_string())
+                    .parse()
+                    .ok()
+                    .filter(|max| *max > 0)
+                    .unwrap_or(4096),
             },
             cors: CorsConfig {
                 allowed_origins: env::var("ORIGINS")
@@ -70,6 +173,80 @@ impl AppConfig {
                     .map(|s| s.trim().to_string())
                     .collect(),
             },
+            sources: SourceConfig {
+                origin_url: env::var("ORIGIN_URL").ok().filter(|v| !v.is_empty()),
+                fetch_timeout_seconds: env::var("FETCH_TIMEOUT")
+                    .unwrap_or_else(|_| "10".to_string())
+                    .parse()
+                    .unwrap_or(10),
+                allowed_hosts: env::var("REMOTE_ALLOWED_HOSTS")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|s| s.trim().to_lowercase())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+                allow_private_networks: env::var("REMOTE_ALLOW_PRIVATE")
+                    .map(|v| v == "true" || v == "1")
+                    .unwrap_or(false),
+                max_redirects: env::var("REMOTE_MAX_REDIRECTS")
+                    .unwrap_or_else(|_| "3".to_string())
+                    .parse()
+                    .unwrap_or(3),
+            },
+            signing: SigningConfig {
+                key: env::var("SIGNING_KEY").ok().filter(|v| !v.is_empty()),
+            },
+            s3: S3Config {
+                endpoint: env::var("S3_ENDPOINT").ok().filter(|v| !v.is_empty()),
+                region: env::var("S3_REGION")
+                    .or_else(|_| env::var("AWS_REGION"))
+                    .unwrap_or_else(|_| "us-east-1".to_string()),
+                access_key_id: env::var("AWS_ACCESS_KEY_ID").ok(),
+                secret_access_key: env::var("AWS_SECRET_ACCESS_KEY").ok(),
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+                allowed_buckets: env::var("S3_ALLOWED_BUCKETS")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+                event_source_prefix: env::var("S3_SOURCE_PREFIX")
+                    .unwrap_or_else(|_| "originals/".to_string()),
+                event_dest_prefix: env::var("S3_DEST_PREFIX")
+                    .unwrap_or_else(|_| "optimized/".to_string()),
+                event_variants: env::var("S3_EVENT_VARIANTS")
+                    .unwrap_or_else(|_| "thumb=w:320,h:240,t:fill;medium=w:1024".to_string())
+                    .split(';')
+                    .filter_map(|entry| entry.split_once('='))
+                    .map(|(name, options)| (name.trim().to_string(), options.trim().to_string()))
+                    .filter(|(name, _)| !name.is_empty())
+                    .collect(),
+            },
+            presets: PresetsConfig::default(),
+            cache: CacheConfig {
+                default_control: env::var("CACHE_CONTROL").ok().filter(|v| !v.is_empty()),
+                routes: env::var("CACHE_CONTROL_ROUTES")
+                    .unwrap_or_else(|_| "/img=public, max-age=31536000, immutable".to_string())
+                    .split(';')
+                    .filter_map(|entry| entry.split_once('='))
+                    .map(|(route, value)| (route.trim().to_string(), value.trim().to_string()))
+                    .collect(),
+            },
+            result_cache: ResultCacheConfig {
+                memory_bytes: env::var("RESULT_CACHE_MB")
+                    .unwrap_or_else(|_| "64".to_string())
+                    .parse::<usize>()
+                    .unwrap_or(64)
+                    * 1024
+                    * 1024,
+                disk_dir: env::var("RESULT_CACHE_DIR").ok().filter(|v| !v.is_empty()),
+                disk_bytes: env::var("RESULT_CACHE_DISK_MB")
+                    .unwrap_or_else(|_| "1024".to_string())
+                    .parse::<u64>()
+                    .unwrap_or(1024)
+                    * 1024
+                    * 1024,
+            },
         }
     }
 
@@ -81,3 +258,63 @@ impl AppConfig {
         env::var("AWS_LAMBDA_RUNTIME_API").is_ok()
     }
 }
+
+// Presets come from PRESETS_FILE (.toml or .json, one table per preset) and/or
+// PRESETS (`card=w:640,h:480,t:fill;thumb=w:320`); inline entries win
+fn load_presets() -> Result<HashMap<String, HashMap<String, String>>, String> {
+    let mut presets = HashMap::new();
+
+    if let Ok(path) = env::var("PRESETS_FILE").map(|p| p.trim().to_string())
+        && !path.is_empty()
+    {
+        let loaded = read_presets_file(&path).map_err(|e| format!("{}: {}", path, e))?;
+        presets.extend(loaded);
+    }
+
+    for entry in env::var("PRESETS").unwrap_or_default().split(';') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let Some((name, options)) = entry.split_once('=') else {
+            return Err(format!("Preset sin nombre en PRESETS: {}", entry));
+        };
+        let options = options
+            .split(',')
+            .map(|pair| {
+                pair.split_once(':')
+                    .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                    .ok_or_else(|| format!("Opcion invalida en preset {}: {}", name.trim(), pair))
+            })
+            .collect::<Result<_, _>>()?;
+        presets.insert(name.trim().to_string(), options);
+    }
+
+    Ok(presets)
+}
+
+fn read_presets_file(path: &str) -> Result<HashMap<String, HashMap<String, String>>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    let raw: HashMap<String, HashMap<String, serde_json::Value>> = if path.ends_with(".toml") {
+        toml::from_str(&content).map_err(|e| e.to_string())?
+    } else {
+        serde_json::from_str(&content).map_err(|e| e.to_string())?
+    };
+
+    Ok(raw
+        .into_iter()
+        .map(|(name, options)| {
+            let options = options
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::String(s) => s,
+                        other => other.to_string(),
+                    };
+                    (key, value)
+                })
+                .collect();
+            (name, options)
+        })
+        .collect())
+}
@@ -1,6 +1,16 @@
-use crate::config::{AppConfig, CorsConfig};
+use crate::config::{AppConfig, CacheConfig, CorsConfig, S3Config};
+use crate::hashing::DEFAULT_SIMILAR_THRESHOLD;
 use crate::models::*;
+use crate::palette::{DEFAULT_PALETTE_COLORS, MAX_PALETTE_COLORS};
+use crate::placeholder::{DEFAULT_BLURHASH_COMPONENTS, parse_placeholder_kind};
 use crate::services::ImageCompressionService;
+use crate::signing::verify_signature;
+use crate::sources::SourceFetcher;
+use crate::storage::{S3Location, S3Storage};
+use crate::tonemap::parse_tone_map;
+use crate::utils::{
+    build_zip, css_hex, html_escape, parse_hex_color, quality_for_dpr, safe_filename,
+};
 use base64::{Engine as _, engine::general_purpose};
 use bytes::Bytes;
 use futures_util::stream;
@@ -8,21 +18,43 @@ use hyper::header::{CONTENT_TYPE, HeaderMap};
 use hyper::{Body, Method, Request, Response, StatusCode};
 use lambda_runtime::{Error, LambdaEvent};
 use serde_json::{Value, json};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::Infallible;
 
+// Per-file `options_<n>` fields in /batch are short query strings
+const MAX_OPTIONS_FIELD_SIZE: usize = 16 * 1024;
+
 pub struct ImageHandler {
     compression_service: ImageCompressionService,
+    source_fetcher: SourceFetcher,
+    storage: S3Storage,
+    signing_key: Option<String>,
+    s3_config: S3Config,
+    presets: HashMap<String, HashMap<String, String>>,
+    cache_config: CacheConfig,
     cors_config: CorsConfig,
     max_image_size: usize,
+    batch_concurrency: usize,
+    batch_max_files: usize,
+    max_url_dimension: u32,
 }
 
 impl ImageHandler {
     pub fn new(config: &AppConfig) -> Self {
         Self {
             compression_service: ImageCompressionService::new(),
+            source_fetcher: SourceFetcher::new(config),
+            storage: S3Storage::new(config),
+            signing_key: config.signing.key.clone(),
+            s3_config: config.s3.clone(),
+            presets: config.presets.presets.clone(),
+            cache_config: config.cache.clone(),
             cors_config: config.cors.clone(),
             max_image_size: config.compression.max_image_size,
+            batch_concurrency: config.compression.batch_concurrency,
+            batch_max_files: config.compression.batch_max_files,
+            max_url_dimension: config.compression.max_url_dimension,
         }
     }
 
@@ -40,24 +72,127 @@ impl ImageHandler {
             return Ok(self.create_cors_response(StatusCode::OK, Body::empty(), origin.as_deref()));
         }
 
+        let accept = req
+            .headers()
+            .get("accept")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+        let if_none_match = req
+            .headers()
+            .get("if-none-match")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+
         if req.method() == Method::POST && req.uri().path() == "/optimize" {
+            let content_type = self.get_content_type(req.headers());
+            let query_params = self.negotiate_format(
+                self.parse_query_params(req.uri().query().unwrap_or("")),
+                accept.as_deref(),
+            );
+            if self.has_remote_source(&query_params) {
+                let result = self
+                    .process_remote_transform(&query_params, false, if_none_match.as_deref())
+                    .await;
+                Ok(self
+                    .create_result_response(
+                        result,
+                        &query_params,
+                        origin.as_deref(),
+                        "/optimize",
+                        if_none_match.as_deref(),
+                    )
+                    .await)
+            } else if self.is_multipart_content_type(content_type.as_deref()) {
+                let result = self
+                    .process_multipart_optimize(
+                        req,
+                        &content_type,
+                        &query_params,
+                        if_none_match.as_deref(),
+                    )
+                    .await
+                    .map_err(HttpError::from);
+                Ok(self
+                    .create_result_response(
+                        result,
+                        &query_params,
+                        origin.as_deref(),
+                        "/optimize",
+                        if_none_match.as_deref(),
+                    )
+                    .await)
+            } else {
+                match self.process_http_request_body(req).await {
+                    Ok(response_body) => Ok(self.create_cors_response(
+                        StatusCode::OK,
+                        Body::from(response_body),
+                        origin.as_deref(),
+                    )),
+                    Err(error) => Ok(self.create_cors_response(
+                        StatusCode::from_u16(error.status).unwrap_or(StatusCode::BAD_REQUEST),
+                        Body::from(self.wrap_error_json(error.message)),
+                        origin.as_deref(),
+                    )),
+                }
+            }
+        } else if req.method() == Method::POST && req.uri().path() == "/resize" {
+            let content_type = self.get_content_type(req.headers());
+            let query_params = self.negotiate_format(
+                self.parse_query_params(req.uri().query().unwrap_or("")),
+                accept.as_deref(),
+            );
+            if self.has_remote_source(&query_params) {
+                let result = self
+                    .process_remote_transform(&query_params, true, if_none_match.as_deref())
+                    .await;
+                Ok(self
+                    .create_result_response(
+                        result,
+                        &query_params,
+                        origin.as_deref(),
+                        "/resize",
+                        if_none_match.as_deref(),
+                    )
+                    .await)
+            } else if self.is_multipart_content_type(content_type.as_deref()) {
+                let result = self
+                    .process_multipart_resize(
+                        req,
+                        &content_type,
+                        &query_params,
+                        if_none_match.as_deref(),
+                    )
+                    .await
+                    .map_err(HttpError::from);
+                Ok(self
+                    .create_result_response(
+                        result,
+                        &query_params,
+                        origin.as_deref(),
+                        "/resize",
+                        if_none_match.as_deref(),
+                    )
+                    .await)
+            } else {
+                Ok(self.create_cors_response(
+                    StatusCode::BAD_REQUEST,
+                    Body::from(
+                        self.wrap_error_json(
+                            "Content-Type debe ser multipart/form-data".to_string(),
+                        ),
+                    ),
+                    origin.as_deref(),
+                ))
+            }
+        } else if req.method() == Method::POST && req.uri().path() == "/srcset" {
             let content_type = self.get_content_type(req.headers());
             if self.is_multipart_content_type(content_type.as_deref()) {
-                match self.process_multipart_optimize(req, &content_type).await {
-                    Ok(result) => {
-                        let resp_content_type = self
-                            .content_type_for_format(&result.output_format)
-                            .to_string();
-                        Ok(self.create_cors_binary_response(
-                            StatusCode::OK,
-                            result.optimized_bytes,
-                            origin.as_deref(),
-                            resp_content_type.as_str(),
-                            result.original_size,
-                            result.optimized_size,
-                            &result.original_format,
-                        ))
-                    }
+                match self.process_multipart_srcset(req, &content_type).await {
+                    Ok(output) => Ok(self.create_cors_bundle_response(
+                        output,
+                        origin.as_deref(),
+                        "srcset.zip",
+                    )),
                     Err(error_body) => Ok(self.create_cors_response(
                         StatusCode::BAD_REQUEST,
                         Body::from(self.wrap_error_json(error_body)),
@@ -65,7 +200,46 @@ impl ImageHandler {
                     )),
                 }
             } else {
-                match self.process_http_request_body(req).await {
+                Ok(self.create_cors_response(
+                    StatusCode::BAD_REQUEST,
+                    Body::from(
+                        self.wrap_error_json(
+                            "Content-Type debe ser multipart/form-data".to_string(),
+                        ),
+                    ),
+                    origin.as_deref(),
+                ))
+            }
+        } else if req.method() == Method::POST && req.uri().path() == "/batch" {
+            let content_type = self.get_content_type(req.headers());
+            if self.is_multipart_content_type(content_type.as_deref()) {
+                match self.process_multipart_batch(req, &content_type).await {
+                    Ok(output) => Ok(self.create_cors_bundle_response(
+                        output,
+                        origin.as_deref(),
+                        "batch.zip",
+                    )),
+                    Err(error_body) => Ok(self.create_cors_response(
+                        StatusCode::BAD_REQUEST,
+                        Body::from(self.wrap_error_json(error_body)),
+                        origin.as_deref(),
+                    )),
+                }
+            } else {
+                Ok(self.create_cors_response(
+                    StatusCode::BAD_REQUEST,
+                    Body::from(
+                        self.wrap_error_json(
+                            "Content-Type debe ser multipart/form-data".to_string(),
+                        ),
+                    ),
+                    origin.as_deref(),
+                ))
+            }
+        } else if req.method() == Method::POST && req.uri().path() == "/placeholder" {
+            let content_type = self.get_content_type(req.headers());
+            if self.is_multipart_content_type(content_type.as_deref()) {
+                match self.process_multipart_placeholder(req, &content_type).await {
                     Ok(response_body) => Ok(self.create_cors_response(
                         StatusCode::OK,
                         Body::from(response_body),
@@ -77,25 +251,130 @@ impl ImageHandler {
                         origin.as_deref(),
                     )),
                 }
+            } else {
+                Ok(self.create_cors_response(
+                    StatusCode::BAD_REQUEST,
+                    Body::from(
+                        self.wrap_error_json(
+                            "Content-Type debe ser multipart/form-data".to_string(),
+                        ),
+                    ),
+                    origin.as_deref(),
+                ))
             }
-        } else if req.method() == Method::POST && req.uri().path() == "/resize" {
+        } else if req.method() == Method::POST && req.uri().path() == "/palette" {
             let content_type = self.get_content_type(req.headers());
             if self.is_multipart_content_type(content_type.as_deref()) {
-                match self.process_multipart_resize(req, &content_type).await {
-                    Ok(result) => {
-                        let resp_content_type = self
-                            .content_type_for_format(&result.output_format)
-                            .to_string();
-                        Ok(self.create_cors_binary_response(
-                            StatusCode::OK,
-                            result.optimized_bytes,
-                            origin.as_deref(),
-                            resp_content_type.as_str(),
-                            result.original_size,
-                            result.optimized_size,
-                            &result.original_format,
-                        ))
-                    }
+                match self.process_multipart_palette(req, &content_type).await {
+                    Ok(response_body) => Ok(self.create_cors_response(
+                        StatusCode::OK,
+                        Body::from(response_body),
+                        origin.as_deref(),
+                    )),
+                    Err(error_body) => Ok(self.create_cors_response(
+                        StatusCode::BAD_REQUEST,
+                        Body::from(self.wrap_error_json(error_body)),
+                        origin.as_deref(),
+                    )),
+                }
+            } else {
+                Ok(self.create_cors_response(
+                    StatusCode::BAD_REQUEST,
+                    Body::from(
+                        self.wrap_error_json(
+                            "Content-Type debe ser multipart/form-data".to_string(),
+                        ),
+                    ),
+                    origin.as_deref(),
+                ))
+            }
+        } else if req.method() == Method::POST && req.uri().path() == "/info" {
+            let content_type = self.get_content_type(req.headers());
+            if self.is_multipart_content_type(content_type.as_deref()) {
+                match self.process_multipart_info(req, &content_type).await {
+                    Ok(response_body) => Ok(self.create_cors_response(
+                        StatusCode::OK,
+                        Body::from(response_body),
+                        origin.as_deref(),
+                    )),
+                    Err(error_body) => Ok(self.create_cors_response(
+                        StatusCode::BAD_REQUEST,
+                        Body::from(self.wrap_error_json(error_body)),
+                        origin.as_deref(),
+                    )),
+                }
+            } else {
+                Ok(self.create_cors_response(
+                    StatusCode::BAD_REQUEST,
+                    Body::from(
+                        self.wrap_error_json(
+                            "Content-Type debe ser multipart/form-data".to_string(),
+                        ),
+                    ),
+                    origin.as_deref(),
+                ))
+            }
+        } else if req.method() == Method::POST && req.uri().path() == "/hash" {
+            let content_type = self.get_content_type(req.headers());
+            if self.is_multipart_content_type(content_type.as_deref()) {
+                match self.process_multipart_hash(req, &content_type).await {
+                    Ok(response_body) => Ok(self.create_cors_response(
+                        StatusCode::OK,
+                        Body::from(response_body),
+                        origin.as_deref(),
+                    )),
+                    Err(error_body) => Ok(self.create_cors_response(
+                        StatusCode::BAD_REQUEST,
+                        Body::from(self.wrap_error_json(error_body)),
+                        origin.as_deref(),
+                    )),
+                }
+            } else {
+                Ok(self.create_cors_response(
+                    StatusCode::BAD_REQUEST,
+                    Body::from(
+                        self.wrap_error_json(
+                            "Content-Type debe ser multipart/form-data".to_string(),
+                        ),
+                    ),
+                    origin.as_deref(),
+                ))
+            }
+        } else if req.method() == Method::POST && req.uri().path() == "/similar" {
+            let content_type = self.get_content_type(req.headers());
+            if self.is_multipart_content_type(content_type.as_deref()) {
+                match self.process_multipart_similar(req, &content_type).await {
+                    Ok(response_body) => Ok(self.create_cors_response(
+                        StatusCode::OK,
+                        Body::from(response_body),
+                        origin.as_deref(),
+                    )),
+                    Err(error_body) => Ok(self.create_cors_response(
+                        StatusCode::BAD_REQUEST,
+                        Body::from(self.wrap_error_json(error_body)),
+                        origin.as_deref(),
+                    )),
+                }
+            } else {
+                Ok(self.create_cors_response(
+                    StatusCode::BAD_REQUEST,
+                    Body::from(
+                        self.wrap_error_json(
+                            "Content-Type debe ser multipart/form-data".to_string(),
+                        ),
+                    ),
+                    origin.as_deref(),
+                ))
+            }
+        } else if req.method() == Method::POST && req.uri().path() == "/icons" {
+            let content_type = self.get_content_type(req.headers());
+            if self.is_multipart_content_type(content_type.as_deref()) {
+                match self.process_multipart_icons(req, &content_type).await {
+                    Ok(output) => Ok(self.create_cors_bundle_response(
+                        output,
+                        origin.as_deref(),
+                        "icons.zip",
+                    )),
                     Err(error_body) => Ok(self.create_cors_response(
                         StatusCode::BAD_REQUEST,
                         Body::from(self.wrap_error_json(error_body)),
@@ -113,6 +392,26 @@ impl ImageHandler {
                     origin.as_deref(),
                 ))
             }
+        } else if req.method() == Method::GET && req.uri().path().starts_with("/img/") {
+            let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
+            let result = self
+                .process_url_transform(
+                    req.uri().path(),
+                    &query_params,
+                    accept.as_deref(),
+                    if_none_match.as_deref(),
+                )
+                .await;
+            let path_params = self.url_transform_params(req.uri().path());
+            Ok(self
+                .create_result_response(
+                    result,
+                    &path_params,
+                    origin.as_deref(),
+                    "/img",
+                    if_none_match.as_deref(),
+                )
+                .await)
         } else if req.method() == Method::POST && req.uri().path() == "/optimize-binary" {
             match self.process_binary_request(req).await {
                 Ok(response_body) => Ok(self.create_cors_response(
@@ -137,6 +436,10 @@ impl ImageHandler {
     pub async fn handle_lambda_event(&self, event: LambdaEvent<Value>) -> Result<Value, Error> {
         dotenv::dotenv().ok();
 
+        if self.is_s3_event(&event.payload) {
+            return self.process_s3_event(&event.payload).await;
+        }
+
         let origin = event
             .payload
             .get("headers")
@@ -156,31 +459,47 @@ impl ImageHandler {
 
         let content_type = self.get_event_header(&event.payload, "content-type");
         let is_multipart = self.is_multipart_content_type(content_type.as_deref());
+        let accept = self.get_event_header(&event.payload, "accept");
+        let if_none_match = self.get_event_header(&event.payload, "if-none-match");
         let query_params = self.get_event_query_params(&event.payload);
 
         let body_bytes = self.get_event_body_bytes(&event.payload)?;
 
         match (method.as_deref(), path.as_deref()) {
             (Some("POST"), Some("/optimize")) => {
-                if is_multipart {
-                    match self
+                let query_params = self.negotiate_format(query_params, accept.as_deref());
+                if self.has_remote_source(&query_params) {
+                    let result = self
+                        .process_remote_transform(&query_params, false, if_none_match.as_deref())
+                        .await;
+                    Ok(self
+                        .create_lambda_result_response(
+                            result,
+                            &query_params,
+                            origin,
+                            "/optimize",
+                            if_none_match.as_deref(),
+                        )
+                        .await)
+                } else if is_multipart {
+                    let result = self
                         .process_multipart_optimize_bytes(
                             content_type.as_deref(),
                             body_bytes,
                             &query_params,
+                            if_none_match.as_deref(),
                         )
                         .await
-                    {
-                        Ok(result) => {
-                            let content_type = self
-                                .content_type_for_format(&result.output_format)
-                                .to_string();
-                            Ok(self.create_lambda_binary_response(origin, result, content_type))
-                        }
-                        Err(error_body) => {
-                            Ok(self.create_lambda_error_response(origin, error_body))
-                        }
-                    }
+                        .map_err(HttpError::from);
+                    Ok(self
+                        .create_lambda_result_response(
+                            result,
+                            &query_params,
+                            origin,
+                            "/optimize",
+                            if_none_match.as_deref(),
+                        )
+                        .await)
                 } else {
                     let body_str = String::from_utf8_lossy(&body_bytes);
                     match self.process_request_body(&body_str).await {
@@ -189,27 +508,63 @@ impl ImageHandler {
                             "headers": self.get_cors_headers(origin),
                             "body": response_json
                         })),
-                        Err(error_json) => {
-                            Ok(self.create_lambda_error_response(origin, error_json))
-                        }
+                        Err(error) => Ok(self.create_lambda_status_error_response(origin, error)),
                     }
                 }
             }
             (Some("POST"), Some("/resize")) => {
+                let query_params = self.negotiate_format(query_params, accept.as_deref());
+                if self.has_remote_source(&query_params) {
+                    let result = self
+                        .process_remote_transform(&query_params, true, if_none_match.as_deref())
+                        .await;
+                    Ok(self
+                        .create_lambda_result_response(
+                            result,
+                            &query_params,
+                            origin,
+                            "/resize",
+                            if_none_match.as_deref(),
+                        )
+                        .await)
+                } else if is_multipart {
+                    let result = self
+                        .process_multipart_resize_bytes(
+                            content_type.as_deref(),
+                            body_bytes,
+                            &query_params,
+                            if_none_match.as_deref(),
+                        )
+                        .await
+                        .map_err(HttpError::from);
+                    Ok(self
+                        .create_lambda_result_response(
+                            result,
+                            &query_params,
+                            origin,
+                            "/resize",
+                            if_none_match.as_deref(),
+                        )
+                        .await)
+                } else {
+                    Ok(self.create_lambda_error_response(
+                        origin,
+                        "Content-Type debe ser multipart/form-data".to_string(),
+                    ))
+                }
+            }
+            (Some("POST"), Some("/srcset")) => {
                 if is_multipart {
                     match self
-                        .process_multipart_resize_bytes(
+                        .process_multipart_srcset_bytes(
                             content_type.as_deref(),
                             body_bytes,
                             &query_params,
                         )
                         .await
                     {
-                        Ok(result) => {
-                            let content_type = self
-                                .content_type_for_format(&result.output_format)
-                                .to_string();
-                            Ok(self.create_lambda_binary_response(origin, result, content_type))
+                        Ok(output) => {
+                            Ok(self.create_lambda_bundle_response(origin, output, "srcset.zip"))
                         }
                         Err(error_body) => {
                             Ok(self.create_lambda_error_response(origin, error_body))
@@ -222,45 +577,244 @@ impl ImageHandler {
                     ))
                 }
             }
-            _ => Ok(json!({
-                "statusCode": 404,
-                "headers": self.get_cors_headers(origin),
-                "body": "Not Found"
-            })),
-        }
-    }
-
-    async fn process_http_request_body(&self, req: Request<Body>) -> Result<String, String> {
-        let body_bytes = hyper::body::to_bytes(req.into_body())
-            .await
-            .map_err(|_| "Error reading request body".to_string())?;
-
-        let body_str = String::from_utf8_lossy(&body_bytes);
-        self.process_request_body(&body_str).await
-    }
-
-    async fn process_binary_request(&self, req: Request<Body>) -> Result<String, String> {
-        let uri = req.uri();
-        let query = uri.query().unwrap_or("");
-        let params: std::collections::HashMap<String, String> =
-            url::form_urlencoded::parse(query.as_bytes())
-                .into_owned()
-                .collect();
-
-        let quality = params
-            .get("quality")
-            .and_then(|q| q.parse().ok())
-            .unwrap_or(85u8);
-
-        let format = params
-            .get("format")
-            .cloned()
-            .unwrap_or_else(|| "auto".to_string());
-
-        let progressive = params
-            .get("progressive")
-            .and_then(|p| p.parse().ok())
-            .unwrap_or(false);
+            (Some("POST"), Some("/batch")) => {
+                if is_multipart {
+                    match self
+                        .process_multipart_batch_bytes(
+                            content_type.as_deref(),
+                            body_bytes,
+                            &query_params,
+                        )
+                        .await
+                    {
+                        Ok(output) => {
+                            Ok(self.create_lambda_bundle_response(origin, output, "batch.zip"))
+                        }
+                        Err(error_body) => {
+                            Ok(self.create_lambda_error_response(origin, error_body))
+                        }
+                    }
+                } else {
+                    Ok(self.create_lambda_error_response(
+                        origin,
+                        "Content-Type debe ser multipart/form-data".to_string(),
+                    ))
+                }
+            }
+            (Some("POST"), Some("/placeholder")) => {
+                if is_multipart {
+                    match self
+                        .process_multipart_placeholder_bytes(
+                            content_type.as_deref(),
+                            body_bytes,
+                            &query_params,
+                        )
+                        .await
+                    {
+                        Ok(response_json) => Ok(json!({
+                            "statusCode": 200,
+                            "headers": self.get_cors_headers(origin),
+                            "body": response_json
+                        })),
+                        Err(error_body) => {
+                            Ok(self.create_lambda_error_response(origin, error_body))
+                        }
+                    }
+                } else {
+                    Ok(self.create_lambda_error_response(
+                        origin,
+                        "Content-Type debe ser multipart/form-data".to_string(),
+                    ))
+                }
+            }
+            (Some("POST"), Some("/palette")) => {
+                if is_multipart {
+                    match self
+                        .process_multipart_palette_bytes(
+                            content_type.as_deref(),
+                            body_bytes,
+                            &query_params,
+                        )
+                        .await
+                    {
+                        Ok(response_json) => Ok(json!({
+                            "statusCode": 200,
+                            "headers": self.get_cors_headers(origin),
+                            "body": response_json
+                        })),
+                        Err(error_body) => {
+                            Ok(self.create_lambda_error_response(origin, error_body))
+                        }
+                    }
+                } else {
+                    Ok(self.create_lambda_error_response(
+                        origin,
+                        "Content-Type debe ser multipart/form-data".to_string(),
+                    ))
+                }
+            }
+            (Some("POST"), Some("/info")) => {
+                if is_multipart {
+                    match self
+                        .process_multipart_info_bytes(
+                            content_type.as_deref(),
+                            body_bytes,
+                            &query_params,
+                        )
+                        .await
+                    {
+                        Ok(response_json) => Ok(json!({
+                            "statusCode": 200,
+                            "headers": self.get_cors_headers(origin),
+                            "body": response_json
+                        })),
+                        Err(error_body) => {
+                            Ok(self.create_lambda_error_response(origin, error_body))
+                        }
+                    }
+                } else {
+                    Ok(self.create_lambda_error_response(
+                        origin,
+                        "Content-Type debe ser multipart/form-data".to_string(),
+                    ))
+                }
+            }
+            (Some("POST"), Some("/hash")) => {
+                if is_multipart {
+                    match self
+                        .process_multipart_hash_bytes(content_type.as_deref(), body_bytes)
+                        .await
+                    {
+                        Ok(response_json) => Ok(json!({
+                            "statusCode": 200,
+                            "headers": self.get_cors_headers(origin),
+                            "body": response_json
+                        })),
+                        Err(error_body) => {
+                            Ok(self.create_lambda_error_response(origin, error_body))
+                        }
+                    }
+                } else {
+                    Ok(self.create_lambda_error_response(
+                        origin,
+                        "Content-Type debe ser multipart/form-data".to_string(),
+                    ))
+                }
+            }
+            (Some("POST"), Some("/similar")) => {
+                if is_multipart {
+                    match self
+                        .process_multipart_similar_bytes(
+                            content_type.as_deref(),
+                            body_bytes,
+                            &query_params,
+                        )
+                        .await
+                    {
+                        Ok(response_json) => Ok(json!({
+                            "statusCode": 200,
+                            "headers": self.get_cors_headers(origin),
+                            "body": response_json
+                        })),
+                        Err(error_body) => {
+                            Ok(self.create_lambda_error_response(origin, error_body))
+                        }
+                    }
+                } else {
+                    Ok(self.create_lambda_error_response(
+                        origin,
+                        "Content-Type debe ser multipart/form-data".to_string(),
+                    ))
+                }
+            }
+            (Some("POST"), Some("/icons")) => {
+                if is_multipart {
+                    match self
+                        .process_multipart_icons_bytes(
+                            content_type.as_deref(),
+                            body_bytes,
+                            &query_params,
+                        )
+                        .await
+                    {
+                        Ok(output) => {
+                            Ok(self.create_lambda_bundle_response(origin, output, "icons.zip"))
+                        }
+                        Err(error_body) => {
+                            Ok(self.create_lambda_error_response(origin, error_body))
+                        }
+                    }
+                } else {
+                    Ok(self.create_lambda_error_response(
+                        origin,
+                        "Content-Type debe ser multipart/form-data".to_string(),
+                    ))
+                }
+            }
+            (Some("GET"), Some(path)) if path.starts_with("/img/") => {
+                let result = self
+                    .process_url_transform(
+                        path,
+                        &query_params,
+                        accept.as_deref(),
+                        if_none_match.as_deref(),
+                    )
+                    .await;
+                let path_params = self.url_transform_params(path);
+                Ok(self
+                    .create_lambda_result_response(
+                        result,
+                        &path_params,
+                        origin,
+                        "/img",
+                        if_none_match.as_deref(),
+                    )
+                    .await)
+            }
+            _ => Ok(json!({
+                "statusCode": 404,
+                "headers": self.get_cors_headers(origin),
+                "body": "Not Found"
+            })),
+        }
+    }
+
+    async fn process_http_request_body(&self, req: Request<Body>) -> Result<String, HttpError> {
+        let body_bytes = hyper::body::to_bytes(req.into_body())
+            .await
+            .map_err(|_| HttpError::new(400, "Error reading request body"))?;
+
+        let body_str = String::from_utf8_lossy(&body_bytes);
+        self.process_request_body(&body_str).await
+    }
+
+    async fn process_binary_request(&self, req: Request<Body>) -> Result<String, String> {
+        let uri = req.uri();
+        let query = uri.query().unwrap_or("");
+        let params: std::collections::HashMap<String, String> =
+            url::form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect();
+        let params = self.resolve_preset(&params)?;
+
+        let dpr = self.parse_dpr(&params)?;
+
+        let quality = params
+            .get("quality")
+            .or_else(|| params.get("q"))
+            .and_then(|q| q.parse().ok())
+            .unwrap_or_else(|| quality_for_dpr(85, dpr));
+
+        let format = params
+            .get("format")
+            .or_else(|| params.get("f"))
+            .cloned()
+            .unwrap_or_else(|| "auto".to_string());
+
+        let progressive = params
+            .get("progressive")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(false);
 
         let aggressive = params
             .get("aggressive")
@@ -280,10 +834,17 @@ impl ImageHandler {
 
         let request = OptimizeRequest {
             image_data: image_base64,
-            quality,
+            quality: Some(quality),
             format,
             progressive,
             aggressive,
+            dpr,
+            image_url: None,
+            preset: None,
+            placeholder: params.get("placeholder").cloned(),
+            lqip: self.parse_bool(params.get("lqip"), "lqip")?,
+            tonemap: params.get("tonemap").cloned(),
+            exposure: self.parse_exposure(&params)?,
         };
 
         let result = self
@@ -295,114 +856,1422 @@ impl ImageHandler {
         serde_json::to_string(&response).map_err(|_| "Error serializing response".to_string())
     }
 
-    async fn process_request_body(&self, body_str: &str) -> Result<String, String> {
+    async fn process_request_body(&self, body_str: &str) -> Result<String, HttpError> {
         let request = if let Ok(req) = serde_json::from_str::<OptimizeRequest>(body_str) {
             req
         } else {
             OptimizeRequest {
                 image_data: body_str.to_string(),
-                quality: 60,
+                quality: Some(60),
                 format: "auto".to_string(),
                 progressive: true,
                 aggressive: true,
+                dpr: 1.0,
+                image_url: None,
+                preset: None,
+                placeholder: None,
+                lqip: false,
+                tonemap: None,
+                exposure: 0.0,
+            }
+        };
+        let request = self.apply_request_preset(request)?;
+
+        let result = match request.image_url.as_deref() {
+            Some(url) => {
+                let source = self.source_fetcher.fetch_remote(url).await?;
+                self.compression_service.optimize_raw_image(
+                    &source.bytes,
+                    &request,
+                    self.max_image_size,
+                )?
+            }
+            None => {
+                self.compression_service
+                    .optimize_image_with_limit(request, self.max_image_size)
+                    .await?
             }
         };
+        let response = self.compression_service.create_response(result);
 
-        let result = self
-            .compression_service
-            .optimize_image_with_limit(request, self.max_image_size)
+        serde_json::to_string(&response)
+            .map_err(|_| HttpError::from("Error serializing response".to_string()))
+    }
+
+    fn has_remote_source(&self, query_params: &HashMap<String, String>) -> bool {
+        query_params.contains_key("url") || query_params.contains_key("source")
+    }
+
+    fn apply_request_preset(
+        &self,
+        mut request: OptimizeRequest,
+    ) -> Result<OptimizeRequest, String> {
+        let Some(name) = request.preset.as_deref() else {
+            return Ok(request);
+        };
+        let preset = self
+            .presets
+            .get(name)
+            .ok_or_else(|| format!("Preset desconocido: {}", name))?;
+
+        if request.quality.is_none()
+            && let Some(q) = preset.get("q")
+        {
+            request.quality = Some(self.parse_quality(Some(q), 1.0)?);
+        }
+        if request.format == "auto"
+            && let Some(f) = preset.get("f")
+        {
+            request.format = f.clone();
+        }
+        if request.dpr == 1.0
+            && let Some(dpr) = preset.get("dpr")
+        {
+            request.dpr = dpr
+                .parse()
+                .map_err(|_| "Parametro dpr invalido (1-4)".to_string())?;
+        }
+
+        Ok(request)
+    }
+
+    async fn process_remote_transform(
+        &self,
+        query_params: &HashMap<String, String>,
+        resize: bool,
+        if_none_match: Option<&str>,
+    ) -> Result<BinaryCompressionResult, HttpError> {
+        let resize = if resize {
+            Some(self.parse_resize_options(query_params)?)
+        } else {
+            None
+        };
+        let options = self.build_transform_options(query_params, resize)?;
+
+        let source = match (query_params.get("url"), query_params.get("source")) {
+            (Some(_), Some(_)) => {
+                return Err(HttpError::new(400, "Usa solo uno de url o source"));
+            }
+            (Some(url), None) => self.source_fetcher.fetch_remote(url).await?,
+            (None, Some(source)) => {
+                self.storage
+                    .get_object(&self.storage.authorized_location(source)?)
+                    .await?
+            }
+            (None, None) => return Err(HttpError::new(400, "Falta url o source")),
+        };
+
+        let mut result = self
+            .optimize_unless_not_modified(source.bytes, &options, query_params, if_none_match)
             .await?;
-        let response = self.compression_service.create_response(result);
+        result.last_modified = source.last_modified;
 
-        serde_json::to_string(&response).map_err(|_| "Error serializing response".to_string())
+        Ok(result)
     }
 
-    async fn process_multipart_optimize(
+    async fn store_result(
         &self,
-        req: Request<Body>,
-        content_type: &Option<String>,
-    ) -> Result<BinaryCompressionResult, String> {
-        let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
-        let options = self.build_transform_options(&query_params, None)?;
+        dest: &str,
+        result: BinaryCompressionResult,
+    ) -> Result<String, HttpError> {
+        let response = self
+            .store_object(self.storage.authorized_location(dest)?, result)
+            .await?;
 
-        let content_type = content_type
-            .as_deref()
-            .ok_or_else(|| "Content-Type requerido".to_string())?;
+        serde_json::to_string(&response)
+            .map_err(|_| HttpError::from("Error serializing response".to_string()))
+    }
 
-        let file_bytes = self
-            .extract_multipart_file_from_body(content_type, req.into_body())
+    async fn store_object(
+        &self,
+        location: S3Location,
+        result: BinaryCompressionResult,
+    ) -> Result<StoredObjectResponse, HttpError> {
+        let content_type = self
+            .content_type_for_format(&result.output_format)
+            .to_string();
+
+        let response = StoredObjectResponse {
+            location: location.uri(),
+            content_type: content_type.clone(),
+            original_size: result.original_size,
+            optimized_size: result.optimized_size,
+            original_format: result.original_format,
+            output_format: result.output_format,
+            width: result.width,
+            height: result.height,
+        };
+
+        self.storage
+            .put_object(&location, result.optimized_bytes, &content_type)
             .await?;
 
-        self.validate_image_size(&file_bytes)?;
+        Ok(response)
+    }
+
+    fn is_s3_event(&self, payload: &Value) -> bool {
+        payload
+            .get("Records")
+            .and_then(|records| records.as_array())
+            .and_then(|records| records.first())
+            .and_then(|record| record.get("eventSource"))
+            .and_then(|source| source.as_str())
+            == Some("aws:s3")
+    }
+
+    // Invoked asynchronously by S3: returning an error makes Lambda retry the event
+    async fn process_s3_event(&self, payload: &Value) -> Result<Value, Error> {
+        let records = payload
+            .get("Records")
+            .and_then(|records| records.as_array())
+            .cloned()
+            .unwrap_or_default();
+
+        let mut responses = Vec::with_capacity(records.len());
+        for record in &records {
+            responses.push(self.process_s3_record(record).await);
+        }
+
+        let errors: Vec<String> = responses
+            .iter()
+            .filter_map(|r| r.error.as_ref().map(|e| format!("{}: {}", r.source, e)))
+            .collect();
+        if !errors.is_empty() {
+            return Err(format!(
+                "{} evento(s) S3 fallaron: {}",
+                errors.len(),
+                errors.join("; ")
+            )
+            .into());
+        }
+        Ok(json!({ "records": responses }))
+    }
+
+    async fn process_s3_record(&self, record: &Value) -> S3EventRecordResponse {
+        let bucket = record["s3"]["bucket"]["name"].as_str().unwrap_or_default();
+        // Keys arrive form-encoded (`+` for spaces)
+        let key = url::form_urlencoded::parse(
+            format!(
+                "k={}",
+                record["s3"]["object"]["key"].as_str().unwrap_or_default()
+            )
+            .as_bytes(),
+        )
+        .next()
+        .map(|(_, v)| v.into_owned())
+        .unwrap_or_default();
+
+        let mut response = S3EventRecordResponse {
+            source: format!("s3://{}/{}", bucket, key),
+            ..Default::default()
+        };
+
+        let event_name = record["eventName"].as_str().unwrap_or_default();
+        if key.starts_with(&self.s3_config.event_dest_prefix) {
+            response.skipped = Some("Variante generada por esta funcion".to_string());
+            return response;
+        }
+        let relative = match key.strip_prefix(&self.s3_config.event_source_prefix) {
+            Some(relative) if event_name.starts_with("ObjectCreated") && !relative.is_empty() => {
+                relative.to_string()
+            }
+            _ => {
+                response.skipped = Some("Fuera del prefijo de originales".to_string());
+                return response;
+            }
+        };
+
+        if let Err(error) = self
+            .generate_s3_variants(bucket, &key, &relative, &mut response)
+            .await
+        {
+            response.error = Some(error.message);
+        }
+
+        response
+    }
+
+    async fn generate_s3_variants(
+        &self,
+        bucket: &str,
+        key: &str,
+        relative: &str,
+        response: &mut S3EventRecordResponse,
+    ) -> Result<(), HttpError> {
+        let variants = self
+            .s3_config
+            .event_variants
+            .iter()
+            .map(|(name, segment)| {
+                let params = self.parse_path_options(segment)?;
+                Ok((name.clone(), self.build_request_options(&params)?))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let source = self
+            .storage
+            .get_object(&S3Location {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            })
+            .await?;
+
+        let options: Vec<TransformOptions> = variants.iter().map(|(_, o)| o.clone()).collect();
+        let results = self
+            .compression_service
+            .generate_variants(source.bytes, options)
+            .await?;
+
+        // originals/products/42.jpg -> optimized/thumb/products/42.webp
+        let stem = match relative.rsplit_once('.') {
+            Some((stem, ext)) if !ext.contains('/') && !stem.ends_with('/') => stem,
+            _ => relative,
+        };
+
+        for ((name, _), result) in variants.iter().zip(results) {
+            let location = S3Location {
+                bucket: bucket.to_string(),
+                key: format!(
+                    "{}{}/{}.{}",
+                    self.s3_config.event_dest_prefix,
+                    name,
+                    stem,
+                    self.extension_for_format(&result.output_format)
+                ),
+            };
+            response
+                .variants
+                .push(self.store_object(location, result).await?);
+        }
+
+        Ok(())
+    }
+
+    async fn process_multipart_optimize(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+        query_params: &HashMap<String, String>,
+        if_none_match: Option<&str>,
+    ) -> Result<BinaryCompressionResult, String> {
+        let options = self.build_transform_options(query_params, None)?;
+
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_body(content_type, req.into_body())
+            .await?;
+
+        self.validate_image_size(&file_bytes)?;
+
+        self.optimize_unless_not_modified(file_bytes, &options, query_params, if_none_match)
+            .await
+    }
+
+    async fn process_multipart_resize(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+        query_params: &HashMap<String, String>,
+        if_none_match: Option<&str>,
+    ) -> Result<BinaryCompressionResult, String> {
+        let resize = self.parse_resize_options(query_params)?;
+        let options = self.build_transform_options(query_params, Some(resize))?;
+
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_body(content_type, req.into_body())
+            .await?;
+
+        self.validate_image_size(&file_bytes)?;
+
+        self.optimize_unless_not_modified(file_bytes, &options, query_params, if_none_match)
+            .await
+    }
+
+    async fn process_multipart_optimize_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+        query_params: &HashMap<String, String>,
+        if_none_match: Option<&str>,
+    ) -> Result<BinaryCompressionResult, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+        let options = self.build_transform_options(query_params, None)?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_bytes(content_type, body_bytes)
+            .await?;
+
+        self.validate_image_size(&file_bytes)?;
+
+        self.optimize_unless_not_modified(file_bytes, &options, query_params, if_none_match)
+            .await
+    }
+
+    async fn process_multipart_resize_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+        query_params: &HashMap<String, String>,
+        if_none_match: Option<&str>,
+    ) -> Result<BinaryCompressionResult, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+        let resize = self.parse_resize_options(query_params)?;
+        let options = self.build_transform_options(query_params, Some(resize))?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_bytes(content_type, body_bytes)
+            .await?;
+
+        self.validate_image_size(&file_bytes)?;
+
+        self.optimize_unless_not_modified(file_bytes, &options, query_params, if_none_match)
+            .await
+    }
+
+    async fn process_multipart_srcset(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+    ) -> Result<BundleOutput, String> {
+        let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
+        let variants = self.build_srcset_variants(&query_params)?;
+
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_body(content_type, req.into_body())
+            .await?;
+
+        self.validate_image_size(&file_bytes)?;
+
+        let results = self
+            .compression_service
+            .generate_variants(file_bytes, variants)
+            .await?;
+        self.build_srcset_output(results, &query_params)
+    }
+
+    async fn process_multipart_srcset_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+        query_params: &HashMap<String, String>,
+    ) -> Result<BundleOutput, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+        let variants = self.build_srcset_variants(query_params)?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_bytes(content_type, body_bytes)
+            .await?;
+
+        self.validate_image_size(&file_bytes)?;
+
+        let results = self
+            .compression_service
+            .generate_variants(file_bytes, variants)
+            .await?;
+        self.build_srcset_output(results, query_params)
+    }
+
+    async fn process_multipart_placeholder(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+    ) -> Result<String, String> {
+        let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
+
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_body(content_type, req.into_body())
+            .await?;
+
+        self.create_placeholder_response(&file_bytes, &query_params)
+            .await
+    }
+
+    async fn process_multipart_placeholder_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+        query_params: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_bytes(content_type, body_bytes)
+            .await?;
+
+        self.create_placeholder_response(&file_bytes, query_params)
+            .await
+    }
+
+    async fn create_placeholder_response(
+        &self,
+        file_bytes: &[u8],
+        query_params: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        self.validate_image_size(file_bytes)?;
+
+        let kind = parse_placeholder_kind(
+            query_params
+                .get("type")
+                .map(|s| s.as_str())
+                .unwrap_or("blurhash"),
+        )?;
+        let components = (
+            self.parse_placeholder_components(
+                query_params.get("x"),
+                DEFAULT_BLURHASH_COMPONENTS.0,
+            )?,
+            self.parse_placeholder_components(
+                query_params.get("y"),
+                DEFAULT_BLURHASH_COMPONENTS.1,
+            )?,
+        );
+
+        let response = self
+            .compression_service
+            .generate_placeholder(file_bytes, kind, components)
+            .await?;
+        serde_json::to_string(&response).map_err(|_| "Error serializing response".to_string())
+    }
+
+    // BlurHash supports 1-9 components per axis
+    fn parse_placeholder_components(
+        &self,
+        value: Option<&String>,
+        default: u32,
+    ) -> Result<u32, String> {
+        match value {
+            None => Ok(default),
+            Some(raw) => raw
+                .parse::<u32>()
+                .ok()
+                .filter(|n| (1..=9).contains(n))
+                .ok_or_else(|| "Componentes de placeholder invalidos (1-9)".to_string()),
+        }
+    }
+
+    async fn process_multipart_palette(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+    ) -> Result<String, String> {
+        let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
+
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_body(content_type, req.into_body())
+            .await?;
+
+        self.create_palette_response(&file_bytes, &query_params)
+            .await
+    }
+
+    async fn process_multipart_palette_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+        query_params: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_bytes(content_type, body_bytes)
+            .await?;
+
+        self.create_palette_response(&file_bytes, query_params)
+            .await
+    }
+
+    async fn create_palette_response(
+        &self,
+        file_bytes: &[u8],
+        query_params: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        self.validate_image_size(file_bytes)?;
+        let colors = self.parse_palette_colors(query_params.get("colors"))?;
+
+        let response = self
+            .compression_service
+            .generate_palette(file_bytes, colors)
+            .await?;
+        serde_json::to_string(&response).map_err(|_| "Error serializing response".to_string())
+    }
+
+    async fn process_multipart_info(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+    ) -> Result<String, String> {
+        let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
+
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_body(content_type, req.into_body())
+            .await?;
+
+        self.create_info_response(&file_bytes, &query_params).await
+    }
+
+    async fn process_multipart_info_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+        query_params: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_bytes(content_type, body_bytes)
+            .await?;
+
+        self.create_info_response(&file_bytes, query_params).await
+    }
+
+    async fn create_info_response(
+        &self,
+        file_bytes: &[u8],
+        query_params: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        self.validate_image_size(file_bytes)?;
+        let palette_colors = if self.parse_bool(query_params.get("palette"), "palette")? {
+            Some(self.parse_palette_colors(query_params.get("colors"))?)
+        } else {
+            None
+        };
+        let page = self.parse_optional_u32(query_params.get("page"))?;
+
+        let response = self
+            .compression_service
+            .image_info(file_bytes, palette_colors, page)
+            .await?;
+        serde_json::to_string(&response).map_err(|_| "Error serializing response".to_string())
+    }
+
+    fn parse_palette_colors(&self, value: Option<&String>) -> Result<usize, String> {
+        match value {
+            None => Ok(DEFAULT_PALETTE_COLORS),
+            Some(raw) => raw
+                .parse::<usize>()
+                .ok()
+                .filter(|n| (1..=MAX_PALETTE_COLORS).contains(n))
+                .ok_or_else(|| format!("Parametro colors invalido (1-{})", MAX_PALETTE_COLORS)),
+        }
+    }
+
+    async fn process_multipart_hash(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+    ) -> Result<String, String> {
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_body(content_type, req.into_body())
+            .await?;
+
+        self.create_hash_response(&file_bytes).await
+    }
+
+    async fn process_multipart_hash_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+    ) -> Result<String, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_bytes(content_type, body_bytes)
+            .await?;
+
+        self.create_hash_response(&file_bytes).await
+    }
+
+    async fn create_hash_response(&self, file_bytes: &[u8]) -> Result<String, String> {
+        self.validate_image_size(file_bytes)?;
+
+        let response = self.compression_service.perceptual_hash(file_bytes).await?;
+        serde_json::to_string(&response).map_err(|_| "Error serializing response".to_string())
+    }
+
+    async fn process_multipart_similar(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+    ) -> Result<String, String> {
+        let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
+
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+        let boundary = multer::parse_boundary(content_type)
+            .map_err(|_| "Boundary invalido en Content-Type".to_string())?;
+
+        let files = self
+            .read_batch_files(multer::Multipart::new(req.into_body(), boundary))
+            .await?;
+        self.create_similar_response(files, &query_params).await
+    }
+
+    async fn process_multipart_similar_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+        query_params: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+        let boundary = multer::parse_boundary(content_type)
+            .map_err(|_| "Boundary invalido en Content-Type".to_string())?;
+        let stream = stream::once(async move { Ok::<Bytes, Infallible>(Bytes::from(body_bytes)) });
+
+        let files = self
+            .read_batch_files(multer::Multipart::new(stream, boundary))
+            .await?;
+        self.create_similar_response(files, query_params).await
+    }
+
+    async fn create_similar_response(
+        &self,
+        files: Vec<BatchFile>,
+        query_params: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let [first, second] = files.as_slice() else {
+            return Err("Se requieren exactamente dos archivos".to_string());
+        };
+        self.validate_image_size(&first.bytes)?;
+        self.validate_image_size(&second.bytes)?;
+
+        let threshold = match query_params.get("threshold") {
+            None => DEFAULT_SIMILAR_THRESHOLD,
+            Some(raw) => raw
+                .parse::<u32>()
+                .ok()
+                .filter(|t| *t <= 64)
+                .ok_or_else(|| "Parametro threshold invalido (0-64)".to_string())?,
+        };
+
+        let response = self
+            .compression_service
+            .compare_images(&first.bytes, &second.bytes, threshold)
+            .await?;
+        serde_json::to_string(&response).map_err(|_| "Error serializing response".to_string())
+    }
+
+    async fn process_multipart_icons(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+    ) -> Result<BundleOutput, String> {
+        let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
+
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_body(content_type, req.into_body())
+            .await?;
+
+        self.build_icons_bundle(&file_bytes, &query_params).await
+    }
+
+    async fn process_multipart_icons_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+        query_params: &HashMap<String, String>,
+    ) -> Result<BundleOutput, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+
+        let file_bytes = self
+            .extract_multipart_file_from_bytes(content_type, body_bytes)
+            .await?;
+
+        self.build_icons_bundle(&file_bytes, query_params).await
+    }
+
+    // ZIP with favicon.ico, PNG icons, site.webmanifest and the <head> tags to paste
+    async fn build_icons_bundle(
+        &self,
+        file_bytes: &[u8],
+        query_params: &HashMap<String, String>,
+    ) -> Result<BundleOutput, String> {
+        self.validate_image_size(file_bytes)?;
+
+        let background = match query_params.get("bg") {
+            None => [255, 255, 255, 255],
+            Some(raw) => parse_hex_color(raw).ok_or_else(|| "Color bg invalido".to_string())?,
+        };
+        let theme = match query_params.get("theme") {
+            None => background,
+            Some(raw) => parse_hex_color(raw).ok_or_else(|| "Color theme invalido".to_string())?,
+        };
+        let name = query_params
+            .get("name")
+            .cloned()
+            .unwrap_or_else(|| "App".to_string());
+        let short_name = query_params
+            .get("short_name")
+            .cloned()
+            .unwrap_or_else(|| name.clone());
+
+        let mut files = self
+            .compression_service
+            .generate_icons(file_bytes, background)
+            .await?;
+
+        let icons: Vec<Value> = files
+            .iter()
+            .filter_map(|(filename, _)| {
+                let size = filename
+                    .strip_prefix("android-chrome-")
+                    .map(|rest| (rest, "any"))
+                    .or_else(|| {
+                        filename
+                            .strip_prefix("maskable-")
+                            .map(|rest| (rest, "maskable"))
+                    });
+                size.map(|(rest, purpose)| {
+                    json!({
+                        "src": format!("/{}", filename),
+                        "sizes": rest.trim_end_matches(".png"),
+                        "type": "image/png",
+                        "purpose": purpose
+                    })
+                })
+            })
+            .collect();
+        let manifest = json!({
+            "name": name,
+            "short_name": short_name,
+            "icons": icons,
+            "theme_color": css_hex(&theme),
+            "background_color": css_hex(&background),
+            "display": "standalone"
+        });
+        let manifest = serde_json::to_vec_pretty(&manifest)
+            .map_err(|_| "Error serializing response".to_string())?;
+
+        let head = format!(
+            "<link rel=\"icon\" href=\"/favicon.ico\" sizes=\"48x48\">\n\
+             <link rel=\"icon\" type=\"image/png\" sizes=\"32x32\" href=\"/favicon-32x32.png\">\n\
+             <link rel=\"icon\" type=\"image/png\" sizes=\"16x16\" href=\"/favicon-16x16.png\">\n\
+             <link rel=\"apple-touch-icon\" sizes=\"180x180\" href=\"/apple-touch-icon.png\">\n\
+             <link rel=\"manifest\" href=\"/site.webmanifest\">\n\
+             <meta name=\"theme-color\" content=\"{}\">\n",
+            css_hex(&theme)
+        );
+
+        files.push(("site.webmanifest".to_string(), manifest));
+        files.push(("head.html".to_string(), head.into_bytes()));
+
+        Ok(BundleOutput::Zip(build_zip(&files)?))
+    }
+
+    // GET /img/{options}/{source}, e.g. /img/w:300,h:200,t:fill,f:webp/products/1.jpg
+    async fn process_url_transform(
+        &self,
+        path: &str,
+        query_params: &HashMap<String, String>,
+        accept: Option<&str>,
+        if_none_match: Option<&str>,
+    ) -> Result<BinaryCompressionResult, HttpError> {
+        if let Some(key) = &self.signing_key {
+            let expires = match query_params.get("exp") {
+                None => None,
+                Some(raw) => Some(
+                    raw.parse::<u64>()
+                        .map_err(|_| HttpError::new(403, "Parametro exp invalido"))?,
+                ),
+            };
+            verify_signature(
+                key,
+                path.strip_prefix("/img").unwrap_or(path),
+                expires,
+                query_params.get("sig").map(|s| s.as_str()),
+            )?;
+        }
+
+        let (options_segment, source) = path
+            .strip_prefix("/img/")
+            .and_then(|rest| rest.split_once('/'))
+            .ok_or_else(|| HttpError::new(400, "Ruta invalida, usa /img/{options}/{source}"))?;
+
+        let params = self.negotiate_format(self.parse_path_options(options_segment)?, accept);
+        // A GET must not write anything, results are only stored from the POST endpoints
+        if self.resolve_preset(&params)?.contains_key("dest") {
+            return Err(HttpError::new(
+                400,
+                "dest no se admite en GET /img, usa POST /optimize o /resize",
+            ));
+        }
+        let mut options = self.build_request_options(&params)?;
+        if let Some(resize) = options.resize.as_mut() {
+            self.clamp_url_resize(resize);
+        }
+
+        let source = self.source_fetcher.fetch_from_origin(source).await?;
+        self.validate_image_size(&source.bytes)?;
+
+        let mut result = self
+            .optimize_unless_not_modified(source.bytes, &options, &params, if_none_match)
+            .await?;
+        result.last_modified = source.last_modified;
+
+        Ok(result)
+    }
+
+    // GET /img is public unless SIGNING_KEY is set, so `w`/`h` (already multiplied by
+    // dpr) are scaled down together until the largest side fits IMG_MAX_DIMENSION
+    fn clamp_url_resize(&self, resize: &mut ResizeOptions) {
+        let largest = resize.width.max(resize.height).unwrap_or(0);
+        if largest <= self.max_url_dimension {
+            return;
+        }
+        let factor = self.max_url_dimension as f64 / largest as f64;
+        let scale = |value: u32| ((value as f64 * factor).round() as u32).max(1);
+        resize.width = resize.width.map(scale);
+        resize.height = resize.height.map(scale);
+    }
+
+    // Options of a `/img/{options}/{source}` path, used to pick the preset's Cache-Control;
+    // `dest` is dropped so the response can never store the result
+    fn url_transform_params(&self, path: &str) -> HashMap<String, String> {
+        let mut params: HashMap<String, String> = path
+            .strip_prefix("/img/")
+            .and_then(|rest| rest.split_once('/'))
+            .and_then(|(segment, _)| self.parse_path_options(segment).ok())
+            .unwrap_or_default();
+        params.remove("dest");
+        params
+    }
+
+    fn parse_path_options(&self, segment: &str) -> Result<HashMap<String, String>, String> {
+        let mut params = HashMap::new();
+        if segment.is_empty() || segment == "_" {
+            return Ok(params);
+        }
+
+        for pair in segment.split(',') {
+            let (key, value) = pair
+                .split_once(':')
+                .ok_or_else(|| format!("Opcion invalida en la ruta: {}", pair))?;
+            let value = url::form_urlencoded::parse(format!("v={}", value).as_bytes())
+                .next()
+                .map(|(_, v)| v.into_owned())
+                .unwrap_or_default();
+            params.insert(key.to_string(), value);
+        }
+
+        Ok(params)
+    }
+
+    async fn process_multipart_batch(
+        &self,
+        req: Request<Body>,
+        content_type: &Option<String>,
+    ) -> Result<BundleOutput, String> {
+        let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
+
+        let content_type = content_type
+            .as_deref()
+            .ok_or_else(|| "Content-Type requerido".to_string())?;
+        let boundary = multer::parse_boundary(content_type)
+            .map_err(|_| "Boundary invalido en Content-Type".to_string())?;
+
+        let files = self
+            .read_batch_files(multer::Multipart::new(req.into_body(), boundary))
+            .await?;
+        self.run_batch(files, &query_params).await
+    }
+
+    async fn process_multipart_batch_bytes(
+        &self,
+        content_type: Option<&str>,
+        body_bytes: Vec<u8>,
+        query_params: &HashMap<String, String>,
+    ) -> Result<BundleOutput, String> {
+        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
+        let boundary = multer::parse_boundary(content_type)
+            .map_err(|_| "Boundary invalido en Content-Type".to_string())?;
+        let stream = stream::once(async move { Ok::<Bytes, Infallible>(Bytes::from(body_bytes)) });
+
+        let files = self
+            .read_batch_files(multer::Multipart::new(stream, boundary))
+            .await?;
+        self.run_batch(files, query_params).await
+    }
+
+    // Collects every `file` part plus optional `options_<n>` fields (query string for file n)
+    // Stops as soon as a part goes over `max_size` instead of buffering it whole first
+    async fn read_field_limited(
+        &self,
+        mut field: multer::Field<'_>,
+        max_size: usize,
+    ) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|_| "Error leyendo archivo".to_string())?
+        {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() > max_size {
+                return Err("Payload demasiado grande".to_string());
+            }
+        }
+        Ok(bytes)
+    }
+
+    async fn read_batch_files(
+        &self,
+        mut multipart: multer::Multipart<'_>,
+    ) -> Result<Vec<BatchFile>, String> {
+        let mut files = Vec::new();
+        let mut overrides: HashMap<usize, HashMap<String, String>> = HashMap::new();
+
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|_| "Error leyendo multipart".to_string())?
+        {
+            let name = field.name().unwrap_or("").to_string();
+            if name == "file" {
+                if files.len() >= self.batch_max_files {
+                    return Err(format!(
+                        "Demasiados archivos (max {})",
+                        self.batch_max_files
+                    ));
+                }
+                let filename = field
+                    .file_name()
+                    .and_then(safe_filename)
+                    .unwrap_or_else(|| format!("file-{}", files.len()));
+                let bytes = self.read_field_limited(field, self.max_image_size).await?;
+                files.push(BatchFile {
+                    filename,
+                    bytes,
+                    overrides: HashMap::new(),
+                });
+            } else if let Some(index) = name
+                .strip_prefix("options_")
+                .and_then(|i| i.parse::<usize>().ok())
+            {
+                let text = self
+                    .read_field_limited(field, MAX_OPTIONS_FIELD_SIZE)
+                    .await?;
+                overrides.insert(
+                    index,
+                    self.parse_query_params(&String::from_utf8_lossy(&text)),
+                );
+            }
+        }
+
+        if files.is_empty() {
+            return Err("No se encontro archivo en multipart".to_string());
+        }
+
+        for (index, params) in overrides {
+            if let Some(file) = files.get_mut(index) {
+                file.overrides = params;
+            }
+        }
+
+        Ok(files)
+    }
+
+    async fn run_batch(
+        &self,
+        files: Vec<BatchFile>,
+        params: &HashMap<String, String>,
+    ) -> Result<BundleOutput, String> {
+        let output = params
+            .get("output")
+            .map(|v| v.as_str())
+            .unwrap_or("json")
+            .to_string();
+        if !matches!(output.as_str(), "json" | "zip" | "multipart") {
+            return Err("Parametro output invalido (json, zip, multipart)".to_string());
+        }
+
+        let mut items = Vec::with_capacity(files.len());
+        let mut jobs = Vec::new();
+        let mut job_indices = Vec::new();
+
+        for (index, file) in files.into_iter().enumerate() {
+            let mut merged = params.clone();
+            merged.extend(file.overrides);
+
+            let prepared = self
+                .validate_image_size(&file.bytes)
+                .and_then(|_| self.build_request_options(&merged));
+
+            let mut item = BatchItemResponse {
+                index,
+                filename: file.filename,
+                ..Default::default()
+            };
+            match prepared {
+                Ok(options) => {
+                    jobs.push((file.bytes, options));
+                    job_indices.push(index);
+                }
+                Err(error) => item.error = Some(error),
+            }
+            items.push(item);
+        }
+
+        let results = self
+            .compression_service
+            .process_batch(jobs, self.batch_concurrency)
+            .await;
+
+        let mut files_out: Vec<Option<Vec<u8>>> = vec![None; items.len()];
+        for (index, result) in job_indices.into_iter().zip(results) {
+            let item = &mut items[index];
+            match result {
+                Ok(result) => {
+                    let stem = item
+                        .filename
+                        .rsplit_once('.')
+                        .map(|(stem, _)| stem)
+                        .unwrap_or(&item.filename);
+                    item.output_filename = Some(format!(
+                        "{}-{}.{}",
+                        index,
+                        stem,
+                        self.extension_for_format(&result.output_format)
+                    ));
+                    item.content_type = Some(
+                        self.content_type_for_format(&result.output_format)
+                            .to_string(),
+                    );
+                    item.original_format = Some(result.original_format);
+                    item.output_format = Some(result.output_format);
+                    item.width = Some(result.width);
+                    item.height = Some(result.height);
+                    item.original_size = Some(result.original_size);
+                    item.optimized_size = Some(result.optimized_size);
+                    if output == "json" {
+                        item.data = Some(general_purpose::STANDARD.encode(&result.optimized_bytes));
+                    }
+                    files_out[index] = Some(result.optimized_bytes);
+                }
+                Err(error) => item.error = Some(error),
+            }
+        }
+
+        match output.as_str() {
+            "zip" => {
+                let manifest = serde_json::to_string(&items)
+                    .map_err(|_| "Error serializing response".to_string())?;
+                let mut entries: Vec<(String, Vec<u8>)> = items
+                    .iter()
+                    .zip(files_out)
+                    .filter_map(|(item, bytes)| Some((item.output_filename.clone()?, bytes?)))
+                    .collect();
+                entries.push(("results.json".to_string(), manifest.into_bytes()));
+                Ok(BundleOutput::Zip(build_zip(&entries)?))
+            }
+            "multipart" => Ok(self.build_multipart_mixed(&items, files_out)),
+            _ => serde_json::to_string(&items)
+                .map(BundleOutput::Json)
+                .map_err(|_| "Error serializing response".to_string()),
+        }
+    }
+
+    fn build_request_options(
+        &self,
+        params: &HashMap<String, String>,
+    ) -> Result<TransformOptions, String> {
+        let params = &*self.resolve_preset(params)?;
+        let resize = if params.contains_key("w") || params.contains_key("h") {
+            Some(self.parse_resize_options(params)?)
+        } else {
+            None
+        };
+        self.build_transform_options(params, resize)
+    }
+
+    fn build_multipart_mixed(
+        &self,
+        items: &[BatchItemResponse],
+        files: Vec<Option<Vec<u8>>>,
+    ) -> BundleOutput {
+        let boundary = format!(
+            "batch-{:x}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0)
+        );
+        let mut body = Vec::new();
+
+        for (item, bytes) in items.iter().zip(files) {
+            body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            match (bytes, &item.output_filename, &item.content_type) {
+                (Some(bytes), Some(filename), Some(content_type)) => {
+                    body.extend_from_slice(
+                        format!(
+                            "Content-Type: {}\r\nContent-Disposition: attachment; filename=\"{}\"\r\nX-Batch-Index: {}\r\n\r\n",
+                            content_type, filename, item.index
+                        )
+                        .as_bytes(),
+                    );
+                    body.extend_from_slice(&bytes);
+                }
+                _ => {
+                    let error = json!({
+                        "index": item.index,
+                        "filename": item.filename,
+                        "error": item.error,
+                    });
+                    body.extend_from_slice(
+                        format!(
+                            "Content-Type: application/json\r\nX-Batch-Index: {}\r\n\r\n{}",
+                            item.index, error
+                        )
+                        .as_bytes(),
+                    );
+                }
+            }
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
 
-        self.compression_service
-            .optimize_image_bytes(&file_bytes, &options)
-            .await
+        BundleOutput::Multipart { boundary, body }
     }
 
-    async fn process_multipart_resize(
+    fn build_srcset_variants(
         &self,
-        req: Request<Body>,
-        content_type: &Option<String>,
-    ) -> Result<BinaryCompressionResult, String> {
-        let query_params = self.parse_query_params(req.uri().query().unwrap_or(""));
-        let resize = self.parse_resize_options(&query_params)?;
-        let options = self.build_transform_options(&query_params, Some(resize))?;
+        params: &HashMap<String, String>,
+    ) -> Result<Vec<TransformOptions>, String> {
+        // Resolved once so the preset's h/dpr can't come back in every variant
+        let params = &*self.resolve_preset(params)?;
+        let widths = self.parse_srcset_widths(params)?;
+        let formats = match params.get("formats") {
+            None => vec![None],
+            Some(raw) => {
+                let mut formats = Vec::new();
+                for format in raw.split(',') {
+                    let format = self.parse_output_format(Some(&format.trim().to_string()))?;
+                    if !formats.contains(&format) {
+                        formats.push(format);
+                    }
+                }
+                formats
+            }
+        };
+        if formats.len() * widths.len() > 40 {
+            return Err("Se permiten hasta 40 variantes (formatos x anchos)".to_string());
+        }
 
-        let content_type = content_type
-            .as_deref()
-            .ok_or_else(|| "Content-Type requerido".to_string())?;
+        let mut variants = Vec::new();
+        for format in &formats {
+            for width in &widths {
+                let mut variant_params = params.clone();
+                variant_params.remove("h");
+                variant_params.remove("dpr");
+                variant_params.insert("w".to_string(), width.to_string());
+                variant_params.insert("t".to_string(), "fit".to_string());
+
+                let resize = self.parse_resize_options(&variant_params)?;
+                let mut options = self.build_transform_options(&variant_params, Some(resize))?;
+                options.output_format = format.clone();
+                variants.push(options);
+            }
+        }
 
-        let file_bytes = self
-            .extract_multipart_file_from_body(content_type, req.into_body())
-            .await?;
+        Ok(variants)
+    }
 
-        self.validate_image_size(&file_bytes)?;
+    fn parse_srcset_widths(&self, params: &HashMap<String, String>) -> Result<Vec<u32>, String> {
+        let mut widths = match params.get("widths") {
+            Some(raw) => raw
+                .split(',')
+                .map(|w| {
+                    w.trim()
+                        .parse::<u32>()
+                        .map_err(|_| "Parametro widths invalido".to_string())
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => {
+                let min = self.parse_optional_u32(params.get("min"))?;
+                let max = self.parse_optional_u32(params.get("max"))?;
+                let step = self.parse_optional_u32(params.get("step"))?.unwrap_or(160);
+                match (min, max) {
+                    (Some(min), Some(max)) if min <= max && step > 0 => {
+                        if !(1..=8000).contains(&min) || !(1..=8000).contains(&max) {
+                            return Err("Los anchos deben estar entre 1 y 8000".to_string());
+                        }
+                        // One past the limit is enough for the count check below
+                        (min..=max).step_by(step as usize).take(21).collect()
+                    }
+                    _ => return Err("Debes enviar widths o min/max/step validos".to_string()),
+                }
+            }
+        };
 
-        self.compression_service
-            .optimize_image_bytes(&file_bytes, &options)
-            .await
+        widths.sort_unstable();
+        widths.dedup();
+
+        if widths.is_empty() || widths.len() > 20 {
+            return Err("Se permiten entre 1 y 20 anchos".to_string());
+        }
+        if widths.iter().any(|w| !(1..=8000).contains(w)) {
+            return Err("Los anchos deben estar entre 1 y 8000".to_string());
+        }
+
+        Ok(widths)
     }
 
-    async fn process_multipart_optimize_bytes(
+    fn build_srcset_output(
         &self,
-        content_type: Option<&str>,
-        body_bytes: Vec<u8>,
-        query_params: &HashMap<String, String>,
-    ) -> Result<BinaryCompressionResult, String> {
-        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
-        let options = self.build_transform_options(query_params, None)?;
+        results: Vec<BinaryCompressionResult>,
+        params: &HashMap<String, String>,
+    ) -> Result<BundleOutput, String> {
+        let as_zip = match params.get("output").map(|v| v.as_str()).unwrap_or("json") {
+            "json" => false,
+            "zip" => true,
+            _ => return Err("Parametro output invalido (json, zip)".to_string()),
+        };
+        let sizes = html_escape(params.get("sizes").map(|s| s.as_str()).unwrap_or("100vw"));
+        let alt = html_escape(params.get("alt").map(|s| s.as_str()).unwrap_or(""));
+
+        let original_format = results
+            .first()
+            .map(|r| r.original_format.clone())
+            .unwrap_or_default();
+        let original_size = results.first().map(|r| r.original_size).unwrap_or(0);
+
+        let mut variants: Vec<SrcsetVariant> = Vec::new();
+        let mut files = Vec::new();
+        let mut format_order: Vec<String> = Vec::new();
+
+        for result in results {
+            // Without enlarge, widths above the source collapse into the same output
+            if variants
+                .iter()
+                .any(|v| v.format == result.output_format && v.width == result.width)
+            {
+                continue;
+            }
+            if !format_order.contains(&result.output_format) {
+                format_order.push(result.output_format.clone());
+            }
 
-        let file_bytes = self
-            .extract_multipart_file_from_bytes(content_type, body_bytes)
-            .await?;
+            let filename = format!(
+                "image-{}w.{}",
+                result.width,
+                self.extension_for_format(&result.output_format)
+            );
+            variants.push(SrcsetVariant {
+                filename: filename.clone(),
+                format: result.output_format.clone(),
+                content_type: self
+                    .content_type_for_format(&result.output_format)
+                    .to_string(),
+                width: result.width,
+                height: result.height,
+                size: result.optimized_size,
+                data: (!as_zip).then(|| general_purpose::STANDARD.encode(&result.optimized_bytes)),
+            });
+            files.push((filename, result.optimized_bytes));
+        }
 
-        self.validate_image_size(&file_bytes)?;
+        let srcset: BTreeMap<String, String> = format_order
+            .iter()
+            .map(|format| {
+                let entries: Vec<String> = variants
+                    .iter()
+                    .filter(|v| &v.format == format)
+                    .map(|v| format!("{} {}w", v.filename, v.width))
+                    .collect();
+                (format.clone(), entries.join(", "))
+            })
+            .collect();
 
-        self.compression_service
-            .optimize_image_bytes(&file_bytes, &options)
-            .await
+        let picture = self.build_picture_snippet(&variants, &format_order, &srcset, &sizes, &alt);
+
+        let response = SrcsetResponse {
+            original_format,
+            original_size,
+            variants,
+            srcset,
+            picture,
+        };
+        let manifest = serde_json::to_string(&response)
+            .map_err(|_| "Error serializing response".to_string())?;
+
+        if as_zip {
+            files.push(("manifest.json".to_string(), manifest.into_bytes()));
+            Ok(BundleOutput::Zip(build_zip(&files)?))
+        } else {
+            Ok(BundleOutput::Json(manifest))
+        }
     }
 
-    async fn process_multipart_resize_bytes(
+    fn build_picture_snippet(
         &self,
-        content_type: Option<&str>,
-        body_bytes: Vec<u8>,
-        query_params: &HashMap<String, String>,
-    ) -> Result<BinaryCompressionResult, String> {
-        let content_type = content_type.ok_or_else(|| "Content-Type requerido".to_string())?;
-        let resize = self.parse_resize_options(query_params)?;
-        let options = self.build_transform_options(query_params, Some(resize))?;
+        variants: &[SrcsetVariant],
+        format_order: &[String],
+        srcset: &BTreeMap<String, String>,
+        sizes: &str,
+        alt: &str,
+    ) -> String {
+        let Some((fallback_format, source_formats)) = format_order.split_last() else {
+            return String::new();
+        };
 
-        let file_bytes = self
-            .extract_multipart_file_from_bytes(content_type, body_bytes)
-            .await?;
+        let mut picture = String::from("<picture>\n");
+        for format in source_formats {
+            picture.push_str(&format!(
+                "  <source type=\"{}\" srcset=\"{}\" sizes=\"{}\">\n",
+                self.content_type_for_format(format),
+                srcset.get(format).map(|s| s.as_str()).unwrap_or(""),
+                sizes
+            ));
+        }
 
-        self.validate_image_size(&file_bytes)?;
+        if let Some(largest) = variants
+            .iter()
+            .filter(|v| &v.format == fallback_format)
+            .max_by_key(|v| v.width)
+        {
+            picture.push_str(&format!(
+                "  <img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"{}\">\n",
+                largest.filename,
+                srcset.get(fallback_format).map(|s| s.as_str()).unwrap_or(""),
+                sizes,
+                largest.width,
+                largest.height,
+                alt
+            ));
+        }
+        picture.push_str("</picture>");
 
-        self.compression_service
-            .optimize_image_bytes(&file_bytes, &options)
-            .await
+        picture
     }
 
     fn create_cors_response(
@@ -417,7 +2286,7 @@ impl ImageHandler {
             .status(status)
             .header("Content-Type", "application/json")
             .header("Access-Control-Allow-Origin", allowed_origin)
-            .header("Access-Control-Allow-Methods", "POST, OPTIONS")
+            .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
             .header(
                 "Access-Control-Allow-Headers",
                 "Content-Type, Authorization",
@@ -428,36 +2297,182 @@ impl ImageHandler {
 
     fn create_cors_binary_response(
         &self,
-        status: StatusCode,
-        body_bytes: Vec<u8>,
+        result: BinaryCompressionResult,
         origin: Option<&str>,
-        content_type: &str,
-        original_size: usize,
-        optimized_size: usize,
-        original_format: &str,
+        cache_control: Option<&str>,
+        not_modified: bool,
     ) -> Response<Body> {
         let allowed_origin = self.get_allowed_origin(origin);
+        let content_type = self.content_type_for_format(&result.output_format);
 
-        Response::builder()
-            .status(status)
-            .header("Content-Type", content_type)
+        let mut builder = Response::builder()
             .header("Access-Control-Allow-Origin", allowed_origin)
-            .header("Access-Control-Allow-Methods", "POST, OPTIONS")
+            .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
             .header(
                 "Access-Control-Allow-Headers",
-                "Content-Type, Authorization",
+                "Content-Type, Authorization, If-None-Match",
             )
             .header(
                 "Access-Control-Expose-Headers",
-                "X-Original-Size, X-Optimized-Size, X-Original-Format",
+                "X-Original-Size, X-Optimized-Size, X-Original-Format, ETag, X-LQIP",
             )
-            .header("X-Original-Size", original_size.to_string())
-            .header("X-Optimized-Size", optimized_size.to_string())
-            .header("X-Original-Format", original_format)
-            .body(Body::from(body_bytes))
+            .header("ETag", &result.etag)
+            .header("Vary", "Accept");
+        if let Some(cache_control) = cache_control {
+            builder = builder.header("Cache-Control", cache_control);
+        }
+        if let Some(last_modified) = &result.last_modified {
+            builder = builder.header("Last-Modified", last_modified);
+        }
+
+        if not_modified {
+            return builder
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap();
+        }
+
+        if let Some(lqip) = &result.lqip {
+            builder = builder.header("X-LQIP", lqip);
+        }
+
+        builder
+            .status(StatusCode::OK)
+            .header("Content-Type", content_type)
+            .header("X-Original-Size", result.original_size.to_string())
+            .header("X-Optimized-Size", result.optimized_size.to_string())
+            .header("X-Original-Format", &result.original_format)
+            .body(Body::from(result.optimized_bytes))
             .unwrap()
     }
 
+    fn cache_control_for(&self, route: &str, params: &HashMap<String, String>) -> Option<String> {
+        params
+            .get("preset")
+            .and_then(|name| self.presets.get(name))
+            .and_then(|preset| preset.get("cache_control"))
+            .or_else(|| self.cache_config.routes.get(route))
+            .or(self.cache_config.default_control.as_ref())
+            .cloned()
+    }
+
+    // The ETag only depends on the source and the options, so a matching If-None-Match
+    // skips decoding and encoding; results bound for `dest` are always produced
+    async fn optimize_unless_not_modified(
+        &self,
+        bytes: Vec<u8>,
+        options: &TransformOptions,
+        query_params: &HashMap<String, String>,
+        if_none_match: Option<&str>,
+    ) -> Result<BinaryCompressionResult, String> {
+        let etag = self.compression_service.etag_for(&bytes, options);
+        if !query_params.contains_key("dest") && self.etag_matches(if_none_match, &etag) {
+            return Ok(BinaryCompressionResult::not_modified(etag));
+        }
+
+        self.compression_service
+            .optimize_image_bytes(bytes, options.clone())
+            .await
+    }
+
+    // Weak comparison as required for If-None-Match (RFC 9110)
+    fn etag_matches(&self, if_none_match: Option<&str>, etag: &str) -> bool {
+        let Some(header) = if_none_match else {
+            return false;
+        };
+        header.split(',').map(str::trim).any(|candidate| {
+            candidate == "*" || candidate.trim_start_matches("W/") == etag.trim_start_matches("W/")
+        })
+    }
+
+    // Binary image, or a JSON summary when the result was written to `dest`
+    async fn create_result_response(
+        &self,
+        result: Result<BinaryCompressionResult, HttpError>,
+        query_params: &HashMap<String, String>,
+        origin: Option<&str>,
+        route: &str,
+        if_none_match: Option<&str>,
+    ) -> Response<Body> {
+        let result = match (result, query_params.get("dest")) {
+            (Ok(result), Some(dest)) => match self.store_result(dest, result).await {
+                Ok(body) => {
+                    return self.create_cors_response(StatusCode::OK, Body::from(body), origin);
+                }
+                Err(error) => Err(error),
+            },
+            (result, _) => result,
+        };
+
+        match result {
+            Ok(result) => {
+                let cache_control = self.cache_control_for(route, query_params);
+                let not_modified = self.etag_matches(if_none_match, &result.etag);
+                self.create_cors_binary_response(
+                    result,
+                    origin,
+                    cache_control.as_deref(),
+                    not_modified,
+                )
+            }
+            Err(error) => self.create_cors_response(
+                StatusCode::from_u16(error.status).unwrap_or(StatusCode::BAD_REQUEST),
+                Body::from(self.wrap_error_json(error.message)),
+                origin,
+            ),
+        }
+    }
+
+    fn create_cors_bundle_response(
+        &self,
+        output: BundleOutput,
+        origin: Option<&str>,
+        filename: &str,
+    ) -> Response<Body> {
+        match output {
+            BundleOutput::Json(body) => {
+                self.create_cors_response(StatusCode::OK, Body::from(body), origin)
+            }
+            BundleOutput::Zip(bytes) => {
+                let allowed_origin = self.get_allowed_origin(origin);
+
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header("Content-Type", "application/zip")
+                    .header(
+                        "Content-Disposition",
+                        format!("attachment; filename=\"{}\"", filename),
+                    )
+                    .header("Access-Control-Allow-Origin", allowed_origin)
+                    .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
+                    .header(
+                        "Access-Control-Allow-Headers",
+                        "Content-Type, Authorization",
+                    )
+                    .body(Body::from(bytes))
+                    .unwrap()
+            }
+            BundleOutput::Multipart { boundary, body } => {
+                let allowed_origin = self.get_allowed_origin(origin);
+
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header(
+                        "Content-Type",
+                        format!("multipart/mixed; boundary={}", boundary),
+                    )
+                    .header("Access-Control-Allow-Origin", allowed_origin)
+                    .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
+                    .header(
+                        "Access-Control-Allow-Headers",
+                        "Content-Type, Authorization",
+                    )
+                    .body(Body::from(body))
+                    .unwrap()
+            }
+        }
+    }
+
     fn get_cors_headers(&self, origin: Option<&str>) -> serde_json::Map<String, Value> {
         let mut headers = serde_json::Map::new();
         let allowed_origin = self.get_allowed_origin(origin);
@@ -469,7 +2484,7 @@ impl ImageHandler {
         );
         headers.insert(
             "Access-Control-Allow-Methods".to_string(),
-            json!("POST, OPTIONS"),
+            json!("GET, POST, OPTIONS"),
         );
         headers.insert(
             "Access-Control-Allow-Headers".to_string(),
@@ -493,7 +2508,7 @@ impl ImageHandler {
         );
         headers.insert(
             "Access-Control-Allow-Methods".to_string(),
-            json!("POST, OPTIONS"),
+            json!("GET, POST, OPTIONS"),
         );
         headers.insert(
             "Access-Control-Allow-Headers".to_string(),
@@ -501,7 +2516,7 @@ impl ImageHandler {
         );
         headers.insert(
             "Access-Control-Expose-Headers".to_string(),
-            json!("X-Original-Size, X-Optimized-Size, X-Original-Format"),
+            json!("X-Original-Size, X-Optimized-Size, X-Original-Format, ETag, X-LQIP"),
         );
         headers
     }
@@ -615,26 +2630,225 @@ impl ImageHandler {
         params: &HashMap<String, String>,
         resize: Option<ResizeOptions>,
     ) -> Result<TransformOptions, String> {
-        let quality = self.parse_quality(params.get("q"))?;
-        let black_and_white = self.parse_bool(params.get("bw"))?;
+        let params = &*self.resolve_preset(params)?;
+        let dpr = self.parse_dpr(params)?;
+        let quality = self.parse_quality(params.get("q"), dpr)?;
+        let black_and_white = self.parse_bool(params.get("bw"), "bw")?;
         let border_radius = self.parse_optional_u32(params.get("br"))?.unwrap_or(0);
         let output_format = self.parse_output_format(params.get("f"))?;
+        let text = self.parse_text_overlay(params)?;
+        let trim = self.parse_trim(params)?;
+        let lqip = self.parse_bool(params.get("lqip"), "lqip")?;
+        let page = self.parse_optional_u32(params.get("page"))?;
+        let tone_map = params
+            .get("tonemap")
+            .map(|value| parse_tone_map(value))
+            .transpose()?;
+        let exposure = self.parse_exposure(params)?;
 
         Ok(TransformOptions {
             quality,
             black_and_white,
             border_radius,
+            trim,
             resize,
+            text,
             output_format,
+            lqip,
+            dpr,
+            page,
+            tone_map,
+            exposure,
         })
     }
 
+    // Without an explicit `f` (in the request or its preset), pick the best format the
+    // client accepts; results written to `dest` are not tied to this client
+    fn negotiate_format(
+        &self,
+        params: HashMap<String, String>,
+        accept: Option<&str>,
+    ) -> HashMap<String, String> {
+        let Some(accept) = accept else {
+            return params;
+        };
+        if params.contains_key("dest") {
+            return params;
+        }
+        // `preset` stays in the params so its Cache-Control still applies
+        let has_format = match self.resolve_preset(&params) {
+            Ok(resolved) => resolved.contains_key("f"),
+            Err(_) => return params,
+        };
+
+        let mut params = params;
+        if !has_format && let Some(format) = self.preferred_format(accept) {
+            params.insert("f".to_string(), format.to_string());
+        }
+
+        params
+    }
+
+    fn preferred_format(&self, accept: &str) -> Option<&'static str> {
+        let accepted: Vec<String> = accept
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.split(';').map(str::trim);
+                let media_type = parts.next()?.to_lowercase();
+                let rejected = parts
+                    .filter_map(|p| p.strip_prefix("q="))
+                    .any(|q| q.parse::<f32>().map(|q| q <= 0.0).unwrap_or(false));
+                (!rejected).then_some(media_type)
+            })
+            .collect();
+
+        ["avif", "webp"]
+            .into_iter()
+            .find(|format| accepted.iter().any(|m| *m == format!("image/{}", format)))
+    }
+
+    // Presets without a size (e.g. only `t` and `q`) get it from the request, so only
+    // the ones that carry `w`/`h` are checked as a resize
+    pub fn validate_presets(&self) -> Result<(), String> {
+        for (name, options) in &self.presets {
+            let resize = if options.contains_key("w") || options.contains_key("h") {
+                Some(self.parse_resize_options(options))
+            } else {
+                None
+            };
+            resize
+                .transpose()
+                .and_then(|resize| self.build_transform_options(options, resize))
+                .map_err(|e| format!("Preset {} invalido: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    // Request params override the preset's; the result no longer carries `preset`
+    fn resolve_preset<'a>(
+        &self,
+        params: &'a HashMap<String, String>,
+    ) -> Result<Cow<'a, HashMap<String, String>>, String> {
+        let Some(name) = params.get("preset") else {
+            return Ok(Cow::Borrowed(params));
+        };
+
+        let mut merged = self
+            .presets
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Preset desconocido: {}", name))?;
+        merged.extend(
+            params
+                .iter()
+                .filter(|(key, _)| key.as_str() != "preset")
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+
+        Ok(Cow::Owned(merged))
+    }
+
+    fn parse_trim(&self, params: &HashMap<String, String>) -> Result<Option<u8>, String> {
+        if !self.parse_bool(params.get("trim"), "trim")? {
+            return Ok(None);
+        }
+
+        match params.get("trim_tol") {
+            None => Ok(Some(10)),
+            Some(raw) => raw
+                .parse::<u8>()
+                .map(Some)
+                .map_err(|_| "Parametro trim_tol invalido (0-255)".to_string()),
+        }
+    }
+
+    fn parse_text_overlay(
+        &self,
+        params: &HashMap<String, String>,
+    ) -> Result<Option<TextOverlay>, String> {
+        let text = match params.get("text") {
+            Some(text) if !text.trim().is_empty() => text.clone(),
+            _ => return Ok(None),
+        };
+
+        if text.chars().count() > 500 {
+            return Err("Parametro text demasiado largo (max 500)".to_string());
+        }
+
+        let size = self.parse_optional_u32(params.get("ts"))?.unwrap_or(32);
+        if !(4..=1000).contains(&size) {
+            return Err("Parametro ts invalido (4-1000)".to_string());
+        }
+
+        let stroke_width = self.parse_optional_u32(params.get("tsw"))?.unwrap_or(0);
+        if stroke_width > 50 {
+            return Err("Parametro tsw invalido (0-50)".to_string());
+        }
+
+        let shadow_offset = match params.get("tsh") {
+            None => 0,
+            Some(raw) => raw
+                .parse::<i32>()
+                .ok()
+                .filter(|v| (-100..=100).contains(v))
+                .ok_or_else(|| "Parametro tsh invalido (-100 a 100)".to_string())?,
+        };
+
+        let gravity = match params.get("tg").map(|v| v.to_lowercase()) {
+            None => Gravity::Center,
+            Some(raw) => match raw.as_str() {
+                "c" | "center" => Gravity::Center,
+                "n" | "north" => Gravity::North,
+                "s" | "south" => Gravity::South,
+                "e" | "east" => Gravity::East,
+                "w" | "west" => Gravity::West,
+                "ne" | "northeast" => Gravity::NorthEast,
+                "nw" | "northwest" => Gravity::NorthWest,
+                "se" | "southeast" => Gravity::SouthEast,
+                "sw" | "southwest" => Gravity::SouthWest,
+                _ => return Err("Parametro tg invalido".to_string()),
+            },
+        };
+
+        let max_width = self.parse_optional_u32(params.get("tmw"))?;
+        if max_width == Some(0) {
+            return Err("Parametro tmw invalido".to_string());
+        }
+
+        Ok(Some(TextOverlay {
+            text,
+            size: size as f32,
+            color: self.parse_color(params.get("tc"), "tc", [255, 255, 255, 255])?,
+            stroke_width,
+            stroke_color: self.parse_color(params.get("tsc"), "tsc", [0, 0, 0, 255])?,
+            shadow_offset,
+            shadow_color: self.parse_color(params.get("tshc"), "tshc", [0, 0, 0, 128])?,
+            gravity,
+            max_width,
+        }))
+    }
+
+    fn parse_color(
+        &self,
+        value: Option<&String>,
+        name: &str,
+        default: [u8; 4],
+    ) -> Result<[u8; 4], String> {
+        match value {
+            None => Ok(default),
+            Some(raw) => parse_hex_color(raw).ok_or_else(|| format!("Parametro {} invalido", name)),
+        }
+    }
+
     fn parse_resize_options(
         &self,
         params: &HashMap<String, String>,
     ) -> Result<ResizeOptions, String> {
-        let width = self.parse_optional_u32(params.get("w"))?;
-        let height = self.parse_optional_u32(params.get("h"))?;
+        let params = &*self.resolve_preset(params)?;
+        let dpr = self.parse_dpr(params)?;
+        let scale = |value: u32| ((value as f32 * dpr).round() as u32).max(1);
+        let width = self.parse_optional_u32(params.get("w"))?.map(scale);
+        let height = self.parse_optional_u32(params.get("h"))?.map(scale);
 
         if width.is_none() && height.is_none() {
             return Err("Debes enviar w o h".to_string());
@@ -647,10 +2861,38 @@ impl ImageHandler {
             _ => return Err("Parametro t invalido".to_string()),
         };
 
+        let filter = match params
+            .get("filter")
+            .map(|v| v.to_lowercase())
+            .as_deref()
+            .unwrap_or("lanczos3")
+        {
+            "nearest" => ResizeFilter::Nearest,
+            "triangle" => ResizeFilter::Triangle,
+            "catmull-rom" | "catmullrom" => ResizeFilter::CatmullRom,
+            "gaussian" => ResizeFilter::Gaussian,
+            "lanczos3" => ResizeFilter::Lanczos3,
+            _ => {
+                return Err(
+                    "Parametro filter invalido (nearest, triangle, catmull-rom, gaussian, lanczos3)"
+                        .to_string(),
+                );
+            }
+        };
+
+        let enlarge = self.parse_bool(params.get("enlarge"), "enlarge")?;
+        let linear = match params.get("linear") {
+            None => None,
+            value => Some(self.parse_bool(value, "linear")?),
+        };
+
         Ok(ResizeOptions {
             width,
             height,
             mode,
+            filter,
+            enlarge,
+            linear,
         })
     }
 
@@ -661,13 +2903,40 @@ impl ImageHandler {
                 "jpeg" | "jpg" => Ok(Some("jpeg".to_string())),
                 "png" => Ok(Some("png".to_string())),
                 "webp" => Ok(Some("webp".to_string())),
-                _ => Err("Parametro f invalido (jpeg, png, webp)".to_string()),
+                "avif" => Ok(Some("avif".to_string())),
+                "jxl" => Ok(Some("jxl".to_string())),
+                "qoi" => Ok(Some("qoi".to_string())),
+                _ => Err("Parametro f invalido (jpeg, png, webp, avif, jxl, qoi)".to_string()),
             },
         }
     }
 
-    fn parse_quality(&self, value: Option<&String>) -> Result<u8, String> {
-        let quality = value.and_then(|v| v.parse::<u8>().ok()).unwrap_or(85);
+    fn parse_dpr(&self, params: &HashMap<String, String>) -> Result<f32, String> {
+        match params.get("dpr") {
+            None => Ok(1.0),
+            Some(raw) => raw
+                .parse::<f32>()
+                .ok()
+                .filter(|dpr| (1.0..=4.0).contains(dpr))
+                .ok_or_else(|| "Parametro dpr invalido (1-4)".to_string()),
+        }
+    }
+
+    fn parse_exposure(&self, params: &HashMap<String, String>) -> Result<f32, String> {
+        match params.get("exposure") {
+            None => Ok(0.0),
+            Some(raw) => raw
+                .parse::<f32>()
+                .ok()
+                .filter(|stops| (-10.0..=10.0).contains(stops))
+                .ok_or_else(|| "Parametro exposure invalido (-10 a 10)".to_string()),
+        }
+    }
+
+    fn parse_quality(&self, value: Option<&String>, dpr: f32) -> Result<u8, String> {
+        let quality = value
+            .and_then(|v| v.parse::<u8>().ok())
+            .unwrap_or_else(|| quality_for_dpr(85, dpr));
 
         if !(1..=100).contains(&quality) {
             return Err("Parametro q invalido".to_string());
@@ -676,13 +2945,13 @@ impl ImageHandler {
         Ok(quality)
     }
 
-    fn parse_bool(&self, value: Option<&String>) -> Result<bool, String> {
+    fn parse_bool(&self, value: Option<&String>, name: &str) -> Result<bool, String> {
         match value {
             None => Ok(false),
             Some(raw) => match raw.to_lowercase().as_str() {
                 "true" | "1" | "yes" => Ok(true),
                 "false" | "0" | "no" => Ok(false),
-                _ => Err("Parametro bw invalido".to_string()),
+                _ => Err(format!("Parametro {} invalido", name)),
             },
         }
     }
@@ -702,13 +2971,23 @@ impl ImageHandler {
             "jpeg" => "image/jpeg",
             "png" => "image/png",
             "webp" => "image/webp",
+            "avif" => "image/avif",
             "gif" => "image/gif",
             "bmp" => "image/bmp",
             "tiff" => "image/tiff",
+            "jxl" => "image/jxl",
+            "qoi" => "image/qoi",
             _ => "application/octet-stream",
         }
     }
 
+    fn extension_for_format<'a>(&self, format: &'a str) -> &'a str {
+        match format {
+            "jpeg" => "jpg",
+            format => format,
+        }
+    }
+
     fn get_event_method(&self, payload: &Value) -> Option<String> {
         payload
             .get("requestContext")
@@ -754,11 +3033,11 @@ impl ImageHandler {
     fn get_event_query_params(&self, payload: &Value) -> HashMap<String, String> {
         let mut params = HashMap::new();
 
-        if let Some(raw) = payload.get("rawQueryString").and_then(|v| v.as_str()) {
-            if !raw.is_empty() {
-                params.extend(self.parse_query_params(raw));
-                return params;
-            }
+        if let Some(raw) = payload.get("rawQueryString").and_then(|v| v.as_str())
+            && !raw.is_empty()
+        {
+            params.extend(self.parse_query_params(raw));
+            return params;
         }
 
         if let Some(map) = payload
@@ -796,9 +3075,29 @@ impl ImageHandler {
         &self,
         origin: Option<&str>,
         result: BinaryCompressionResult,
-        content_type: String,
+        cache_control: Option<String>,
+        not_modified: bool,
     ) -> Value {
-        let mut headers = self.get_cors_headers_with_content_type(origin, &content_type);
+        let content_type = self.content_type_for_format(&result.output_format);
+        let mut headers = self.get_cors_headers_with_content_type(origin, content_type);
+        headers.insert("ETag".to_string(), json!(result.etag));
+        headers.insert("Vary".to_string(), json!("Accept"));
+        if let Some(cache_control) = cache_control {
+            headers.insert("Cache-Control".to_string(), json!(cache_control));
+        }
+        if let Some(last_modified) = &result.last_modified {
+            headers.insert("Last-Modified".to_string(), json!(last_modified));
+        }
+
+        if not_modified {
+            headers.remove("Content-Type");
+            return json!({
+                "statusCode": 304,
+                "headers": headers,
+                "body": ""
+            });
+        }
+
         headers.insert(
             "X-Original-Size".to_string(),
             json!(result.original_size.to_string()),
@@ -811,6 +3110,9 @@ impl ImageHandler {
             "X-Original-Format".to_string(),
             json!(result.original_format),
         );
+        if let Some(lqip) = &result.lqip {
+            headers.insert("X-LQIP".to_string(), json!(lqip));
+        }
 
         let body = general_purpose::STANDARD.encode(&result.optimized_bytes);
 
@@ -822,6 +3124,82 @@ impl ImageHandler {
         })
     }
 
+    async fn create_lambda_result_response(
+        &self,
+        result: Result<BinaryCompressionResult, HttpError>,
+        query_params: &HashMap<String, String>,
+        origin: Option<&str>,
+        route: &str,
+        if_none_match: Option<&str>,
+    ) -> Value {
+        let result = match (result, query_params.get("dest")) {
+            (Ok(result), Some(dest)) => match self.store_result(dest, result).await {
+                Ok(body) => {
+                    return json!({
+                        "statusCode": 200,
+                        "headers": self.get_cors_headers(origin),
+                        "body": body
+                    });
+                }
+                Err(error) => Err(error),
+            },
+            (result, _) => result,
+        };
+
+        match result {
+            Ok(result) => {
+                let cache_control = self.cache_control_for(route, query_params);
+                let not_modified = self.etag_matches(if_none_match, &result.etag);
+                self.create_lambda_binary_response(origin, result, cache_control, not_modified)
+            }
+            Err(error) => self.create_lambda_status_error_response(origin, error),
+        }
+    }
+
+    fn create_lambda_bundle_response(
+        &self,
+        origin: Option<&str>,
+        output: BundleOutput,
+        filename: &str,
+    ) -> Value {
+        match output {
+            BundleOutput::Json(body) => json!({
+                "statusCode": 200,
+                "headers": self.get_cors_headers(origin),
+                "body": body
+            }),
+            BundleOutput::Zip(bytes) => {
+                let mut headers = self.get_cors_headers(origin);
+                headers.insert("Content-Type".to_string(), json!("application/zip"));
+                headers.insert(
+                    "Content-Disposition".to_string(),
+                    json!(format!("attachment; filename=\"{}\"", filename)),
+                );
+
+                json!({
+                    "statusCode": 200,
+                    "headers": headers,
+                    "body": general_purpose::STANDARD.encode(&bytes),
+                    "isBase64Encoded": true
+                })
+            }
+            BundleOutput::Multipart { boundary, body } => {
+                let mut headers = self.get_cors_headers(origin);
+                headers.insert(
+                    "Content-Type".to_string(),
+                    json!(format!("multipart/mixed; boundary={}", boundary)),
+                );
+
+                json!({
+                    "statusCode": 200,
+                    "headers": headers,
+                    "body": general_purpose::STANDARD.encode(&body),
+                    "isBase64Encoded": true
+                })
+            }
+        }
+    }
+
     fn create_lambda_error_response(&self, origin: Option<&str>, error_body: String) -> Value {
         json!({
             "statusCode": 400,
@@ -830,6 +3208,14 @@ impl ImageHandler {
         })
     }
 
+    fn create_lambda_status_error_response(&self, origin: Option<&str>, error: HttpError) -> Value {
+        json!({
+            "statusCode": error.status,
+            "headers": self.get_cors_headers(origin),
+            "body": self.wrap_error_json(error.message)
+        })
+    }
+
     fn wrap_error_json(&self, message: String) -> String {
         json!({ "error": message }).to_string()
     }
@@ -0,0 +1,93 @@
+use crate::models::{HashDistances, ImageHashes};
+use image::{DynamicImage, GrayImage, imageops, imageops::FilterType};
+use std::f64::consts::PI;
+
+const HASH_SIDE: u32 = 8;
+const PHASH_SIDE: usize = 32; // DCT input; the low 8x8 frequencies form the hash
+
+// pHash distance up to which two images count as near-duplicates
+pub const DEFAULT_SIMILAR_THRESHOLD: u32 = 10;
+
+pub fn perceptual_hashes(img: &DynamicImage) -> ImageHashes {
+    let gray = img.to_luma8();
+
+    ImageHashes {
+        ahash: format_hash(average_hash(&gray)),
+        dhash: format_hash(difference_hash(&gray)),
+        phash: format_hash(dct_hash(&gray)),
+    }
+}
+
+pub fn hash_distances(a: &ImageHashes, b: &ImageHashes) -> Result<HashDistances, String> {
+    Ok(HashDistances {
+        ahash: hamming_distance(&a.ahash, &b.ahash)?,
+        dhash: hamming_distance(&a.dhash, &b.dhash)?,
+        phash: hamming_distance(&a.phash, &b.phash)?,
+    })
+}
+
+fn hamming_distance(a: &str, b: &str) -> Result<u32, String> {
+    let parse =
+        |hash: &str| u64::from_str_radix(hash, 16).map_err(|_| format!("Hash invalido: {}", hash));
+    Ok((parse(a)? ^ parse(b)?).count_ones())
+}
+
+fn average_hash(gray: &GrayImage) -> u64 {
+    let small = imageops::resize(gray, HASH_SIDE, HASH_SIDE, FilterType::Triangle);
+    let mean = small.pixels().map(|p| p.0[0] as u32).sum::<u32>() / (HASH_SIDE * HASH_SIDE);
+
+    bits_from(small.pixels().map(|p| p.0[0] as u32 > mean))
+}
+
+// Compares each pixel with its right neighbour, so the input is one column wider
+fn difference_hash(gray: &GrayImage) -> u64 {
+    let small = imageops::resize(gray, HASH_SIDE + 1, HASH_SIDE, FilterType::Triangle);
+
+    bits_from((0..HASH_SIDE).flat_map(|y| {
+        let small = &small;
+        (0..HASH_SIDE).map(move |x| small.get_pixel(x, y).0[0] < small.get_pixel(x + 1, y).0[0])
+    }))
+}
+
+fn dct_hash(gray: &GrayImage) -> u64 {
+    let side = PHASH_SIDE as u32;
+    let small = imageops::resize(gray, side, side, FilterType::Triangle);
+    let pixels: Vec<f64> = small.pixels().map(|p| p.0[0] as f64).collect();
+
+    let low = HASH_SIDE as usize;
+    let cosines: Vec<Vec<f64>> = (0..low)
+        .map(|u| {
+            (0..PHASH_SIDE)
+                .map(|x| ((2 * x + 1) as f64 * u as f64 * PI / (2 * PHASH_SIDE) as f64).cos())
+                .collect()
+        })
+        .collect();
+
+    let mut coefficients = Vec::with_capacity(low * low);
+    for v in 0..low {
+        for u in 0..low {
+            let mut sum = 0.0;
+            for y in 0..PHASH_SIDE {
+                for x in 0..PHASH_SIDE {
+                    sum += pixels[y * PHASH_SIDE + x] * cosines[u][x] * cosines[v][y];
+                }
+            }
+            coefficients.push(sum);
+        }
+    }
+
+    // The DC term only carries overall brightness, keep it out of the median
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    bits_from(coefficients.iter().map(|c| *c > median))
+}
+
+fn bits_from(bits: impl Iterator<Item = bool>) -> u64 {
+    bits.fold(0u64, |hash, bit| (hash << 1) | bit as u64)
+}
+
+fn format_hash(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
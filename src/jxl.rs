@@ -0,0 +1,66 @@
+use image::{ColorType, DynamicImage, ImageDecoder, Limits};
+use jxl_oxide::integration::JxlDecoder;
+use std::io::Cursor;
+use zune_core::bit_depth::BitDepth;
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::EncoderOptions;
+use zune_jpegxl::JxlSimpleEncoder;
+
+const CODESTREAM_SIGNATURE: [u8; 2] = [0xff, 0x0a];
+const CONTAINER_SIGNATURE: [u8; 12] = [
+    0, 0, 0, 0x0c, b'J', b'X', b'L', b' ', 0x0d, 0x0a, 0x87, 0x0a,
+];
+
+pub fn is_jxl(bytes: &[u8]) -> bool {
+    bytes.starts_with(&CODESTREAM_SIGNATURE) || bytes.starts_with(&CONTAINER_SIGNATURE)
+}
+
+// Same limits `image::load_from_memory` applies to the other formats: the header is
+// checked before any pixel is decoded and jxl-oxide's own buffers count against max_alloc
+pub fn decode_jxl(bytes: &[u8]) -> Result<DynamicImage, String> {
+    let mut decoder =
+        JxlDecoder::new(Cursor::new(bytes)).map_err(|_| "JPEG XL invalido".to_string())?;
+
+    let mut limits = Limits::default();
+    let (width, height) = decoder.dimensions();
+    limits
+        .check_dimensions(width, height)
+        .and_then(|_| limits.reserve(decoder.total_bytes()))
+        .and_then(|_| decoder.set_limits(limits))
+        .map_err(|_| "JPEG XL demasiado grande".to_string())?;
+
+    DynamicImage::from_decoder(decoder).map_err(|_| "Error decodificando JPEG XL".to_string())
+}
+
+// The only pure-Rust encoder is lossless, so there is no quality setting
+pub fn encode_jxl(img: &DynamicImage) -> Result<Vec<u8>, String> {
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let has_alpha = img.color().has_alpha();
+    let colorspace = if has_alpha {
+        ColorSpace::RGBA
+    } else {
+        ColorSpace::RGB
+    };
+
+    let (pixels, depth) = match img.color() {
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            let samples = if has_alpha {
+                img.to_rgba16().into_raw()
+            } else {
+                img.to_rgb16().into_raw()
+            };
+            let bytes = samples.iter().flat_map(|s| s.to_ne_bytes()).collect();
+            (bytes, BitDepth::Sixteen)
+        }
+        _ if has_alpha => (img.to_rgba8().into_raw(), BitDepth::Eight),
+        _ => (img.to_rgb8().into_raw(), BitDepth::Eight),
+    };
+
+    let options = EncoderOptions::new(width, height, colorspace, depth);
+    let mut buffer = Vec::new();
+    JxlSimpleEncoder::new(&pixels, options)
+        .encode(&mut buffer)
+        .map_err(|_| "Error codificando JPEG XL (minimo 2x2 px)".to_string())?;
+
+    Ok(buffer)
+}
@@ -2,17 +2,40 @@ use hyper::Server;
 use hyper::service::{make_service_fn, service_fn as hyper_service_fn};
 use lambda_runtime::service_fn;
 use std::convert::Infallible;
+mod cache;
 mod config;
 mod handlers;
+mod hashing;
+mod jxl;
 mod models;
+mod pages;
+mod palette;
+mod placeholder;
 mod services;
+mod signing;
+mod sources;
+mod storage;
+mod svg;
+mod text;
+mod tonemap;
 mod utils;
-use config::AppConfig;
+use config::{AppConfig, PresetsConfig};
 use handlers::ImageHandler;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let config = AppConfig::from_env();
+    let mut config = AppConfig::from_env();
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|a| a.as_str()) == Some("sign") {
+        return signing::run_cli(&config, &args[2..]).map_err(|e| e.into());
+    }
+
+    config.presets =
+        PresetsConfig::from_env().map_err(|e| format!("Error cargando presets: {}", e))?;
+    config.s3.validate_event_prefixes()?;
+    ImageHandler::new(&config).validate_presets()?;
+    cache::init_result_cache(&config);
 
     if config.is_running_on_lambda() {
         println!("🔺 Starting Lambda function");
@@ -27,6 +50,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         println!("💻 Starting local server at {}", config.server_address());
         println!("- Use POST /optimize with JSON or multipart/form-data");
         println!("- Use POST /resize with multipart/form-data");
+        println!("- Use POST /srcset with multipart/form-data");
+        println!("- Use POST /batch with multipart/form-data");
+        println!("- Use POST /placeholder with multipart/form-data");
+        println!("- Use POST /palette with multipart/form-data");
+        println!("- Use POST /info with multipart/form-data");
+        println!("- Use POST /hash with multipart/form-data");
+        println!("- Use POST /similar with multipart/form-data (two files)");
+        println!("- Use POST /icons with multipart/form-data");
+        println!("- Use GET /img/{{options}}/{{source}} to transform from the origin");
         println!(
             "- Max image size: {} MB",
             config.compression.max_image_size / (1024 * 1024) // 50MB
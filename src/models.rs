@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct OptimizeRequest {
+    #[serde(default)]
     pub image_data: String,
-    #[serde(default = "default_quality")]
-    pub quality: u8, // 1-100, default 75
+    #[serde(default)]
+    pub image_url: Option<String>, // Fetched instead of image_data when present
+    #[serde(default)]
+    pub quality: Option<u8>, // 1-100, default 75 (lowered for dpr > 1)
     #[serde(default = "default_format")]
     pub format: String, // "jpeg", "png", "webp", "auto"
     #[allow(dead_code)] // Kept for API compatibility; image crate lacks progressive JPEG support
@@ -12,6 +16,18 @@ pub struct OptimizeRequest {
     pub progressive: bool,
     #[serde(default)]
     pub aggressive: bool, // Aggressive compression
+    #[serde(default = "default_dpr")]
+    pub dpr: f32, // Device pixel ratio 1-4
+    #[serde(default)]
+    pub preset: Option<String>, // Fills q/f/dpr when not set explicitly
+    #[serde(default)]
+    pub placeholder: Option<String>, // "blurhash" or "thumbhash"
+    #[serde(default)]
+    pub lqip: bool, // Tiny data: URI preview alongside the result
+    #[serde(default)]
+    pub tonemap: Option<String>, // "reinhard", "aces" or "none" for HDR/16-bit sources
+    #[serde(default)]
+    pub exposure: f32, // Stops applied before tone mapping
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -23,6 +39,10 @@ pub struct OptimizeResponse {
     pub original_format: String,
     pub output_format: String,
     pub quality_used: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub placeholder: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lqip: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -39,29 +59,83 @@ pub struct CompressionResult {
     pub original_format: String,
     pub output_format: String,
     pub quality_used: u8,
+    pub placeholder: Option<String>,
+    pub lqip: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Serialize, Debug, Clone, Copy)]
 pub enum ResizeMode {
     Fit,
     Fill,
     Force,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Debug, Clone, Copy)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+#[derive(Serialize, Debug, Clone)]
 pub struct ResizeOptions {
     pub width: Option<u32>,
     pub height: Option<u32>,
     pub mode: ResizeMode,
+    pub filter: ResizeFilter,
+    pub enlarge: bool,        // Allow output larger than the source
+    pub linear: Option<bool>, // Resample in linear light; None = only for photos (JPEG)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Debug, Clone)]
 pub struct TransformOptions {
     pub quality: u8,
     pub black_and_white: bool,
     pub border_radius: u32,
+    pub trim: Option<u8>, // Color tolerance for border trimming
     pub resize: Option<ResizeOptions>,
+    pub text: Option<TextOverlay>,
     pub output_format: Option<String>,
+    pub lqip: bool,
+    pub dpr: f32,                  // Only used to rasterise SVG sources without w/h
+    pub page: Option<u32>,         // 0-based page/frame of multi-image TIFF and ICO
+    pub tone_map: Option<ToneMap>, // HDR/16-bit sources; float inputs default to ACES
+    pub exposure: f32,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub enum ToneMap {
+    None,
+    Reinhard,
+    Aces,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub enum Gravity {
+    Center,
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TextOverlay {
+    pub text: String,
+    pub size: f32,
+    pub color: [u8; 4],
+    pub stroke_width: u32,
+    pub stroke_color: [u8; 4],
+    pub shadow_offset: i32,
+    pub shadow_color: [u8; 4],
+    pub gravity: Gravity,
+    pub max_width: Option<u32>,
 }
 
 #[derive(Debug, Clone)]
@@ -71,12 +145,211 @@ pub struct BinaryCompressionResult {
     pub optimized_size: usize,
     pub original_format: String,
     pub output_format: String,
+    pub width: u32,
+    pub height: u32,
+    pub etag: String, // Strong validator from source bytes + options
+    pub last_modified: Option<String>, // Propagated from the source when it has one
+    pub lqip: Option<String>, // data: URI, only when requested
+}
+
+impl BinaryCompressionResult {
+    // Placeholder for a 304, only the ETag is sent back
+    pub fn not_modified(etag: String) -> Self {
+        Self {
+            optimized_bytes: Vec::new(),
+            original_size: 0,
+            optimized_size: 0,
+            original_format: String::new(),
+            output_format: String::new(),
+            width: 0,
+            height: 0,
+            etag,
+            last_modified: None,
+            lqip: None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SrcsetVariant {
+    pub filename: String,
+    pub format: String,
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>, // Base64, omitted inside ZIP archives
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SrcsetResponse {
+    pub original_format: String,
+    pub original_size: usize,
+    pub variants: Vec<SrcsetVariant>,
+    pub srcset: BTreeMap<String, String>, // format -> srcset attribute
+    pub picture: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchFile {
+    pub filename: String,
+    pub bytes: Vec<u8>,
+    pub overrides: HashMap<String, String>, // Per-file query params
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct BatchItemResponse {
+    pub index: usize,
+    pub filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optimized_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StoredObjectResponse {
+    pub location: String, // s3://bucket/key
+    pub content_type: String,
+    pub original_size: usize,
+    pub optimized_size: usize,
+    pub original_format: String,
+    pub output_format: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct S3EventRecordResponse {
+    pub source: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<StoredObjectResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub enum PlaceholderKind {
+    #[serde(rename = "blurhash")]
+    BlurHash,
+    #[serde(rename = "thumbhash")]
+    ThumbHash,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PlaceholderResponse {
+    pub placeholder: String,
+    #[serde(rename = "type")]
+    pub kind: PlaceholderKind,
+    pub width: u32, // Source dimensions, to reserve the layout box
+    pub height: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PaletteColor {
+    pub hex: String,
+    pub share: f64, // Fraction of opaque pixels, 0-1
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PaletteResponse {
+    pub dominant: PaletteColor,
+    pub palette: Vec<PaletteColor>, // Sorted by share, largest first
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InfoResponse {
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub size: usize,
+    pub color_type: String,
+    pub has_alpha: bool,
+    pub pages: u32, // Pages (TIFF) or frames (ICO); 1 for single-image formats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<PaletteResponse>,
 }
 
-fn default_quality() -> u8 {
+#[derive(Serialize, Debug, Clone)]
+pub struct ImageHashes {
+    pub ahash: String, // 64-bit hashes as 16 hex chars
+    pub dhash: String,
+    pub phash: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HashDistances {
+    pub ahash: u32, // Hamming distance, 0-64
+    pub dhash: u32,
+    pub phash: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SimilarResponse {
+    pub hashes: Vec<ImageHashes>,
+    pub distance: HashDistances,
+    pub threshold: u32,
+    pub similar: bool, // pHash distance <= threshold
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl HttpError {
+    pub fn new(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<String> for HttpError {
+    fn from(message: String) -> Self {
+        Self {
+            status: 400,
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BundleOutput {
+    Json(String),
+    Zip(Vec<u8>),
+    Multipart { boundary: String, body: Vec<u8> },
+}
+
+pub fn default_quality() -> u8 {
     75
 }
 
+fn default_dpr() -> f32 {
+    1.0
+}
+
 fn default_format() -> String {
     "auto".to_string()
 }
@@ -0,0 +1,147 @@
+use std::borrow::Cow;
+
+// A corrupt or looping IFD chain must not keep us walking forever
+const MAX_PAGES: usize = 10_000;
+
+struct TiffLayout {
+    little_endian: bool,
+    big_tiff: bool,
+}
+
+impl TiffLayout {
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let little_endian = match bytes.get(..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err("TIFF invalido".to_string()),
+        };
+        let layout = Self {
+            little_endian,
+            big_tiff: false,
+        };
+        match layout.read(bytes, 2, 2)? {
+            42 => Ok(layout),
+            43 => Ok(Self {
+                big_tiff: true,
+                ..layout
+            }),
+            _ => Err("TIFF invalido".to_string()),
+        }
+    }
+
+    fn read(&self, bytes: &[u8], at: usize, len: usize) -> Result<u64, String> {
+        let field = bytes
+            .get(at..at.saturating_add(len))
+            .ok_or_else(|| "TIFF truncado".to_string())?;
+        let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+        Ok(if self.little_endian {
+            field.iter().rev().fold(0, fold)
+        } else {
+            field.iter().fold(0, fold)
+        })
+    }
+
+    fn write(&self, bytes: &mut [u8], at: usize, len: usize, value: u64) {
+        for i in 0..len {
+            let shift = if self.little_endian { i } else { len - 1 - i } * 8;
+            bytes[at + i] = (value >> shift) as u8;
+        }
+    }
+
+    // Where the header stores the offset of the first IFD, and that field's width
+    fn first_ifd_field(&self) -> (usize, usize) {
+        if self.big_tiff { (8, 8) } else { (4, 4) }
+    }
+
+    fn ifd_offsets(&self, bytes: &[u8]) -> Result<Vec<u64>, String> {
+        let (count_len, entry_len, offset_len) = if self.big_tiff {
+            (8, 20, 8)
+        } else {
+            (2, 12, 4)
+        };
+        let (at, len) = self.first_ifd_field();
+
+        let mut offsets = Vec::new();
+        let mut next = self.read(bytes, at, len)?;
+        while next != 0 {
+            if offsets.len() >= MAX_PAGES || offsets.contains(&next) {
+                return Err("TIFF invalido".to_string());
+            }
+            offsets.push(next);
+            let ifd = next as usize;
+            let entries = self.read(bytes, ifd, count_len)? as usize;
+            let next_field = entries
+                .checked_mul(entry_len)
+                .and_then(|size| size.checked_add(ifd)?.checked_add(count_len))
+                .ok_or_else(|| "TIFF invalido".to_string())?;
+            next = self.read(bytes, next_field, offset_len)?;
+        }
+
+        Ok(offsets)
+    }
+}
+
+pub fn page_count(bytes: &[u8], format: &str) -> Result<u32, String> {
+    match format {
+        "tiff" => {
+            let layout = TiffLayout::parse(bytes)?;
+            Ok(layout.ifd_offsets(bytes)?.len() as u32)
+        }
+        "ico" => Ok(ico_entry_count(bytes)? as u32),
+        _ => Ok(1),
+    }
+}
+
+// Rewrites the container so the requested page (0-based) is the one decoders read first:
+// TIFF gets its first-IFD pointer moved, ICO is rebuilt with a single directory entry
+pub fn select_page<'a>(bytes: &'a [u8], format: &str, page: u32) -> Result<Cow<'a, [u8]>, String> {
+    let out_of_range = || format!("Pagina {} fuera de rango", page);
+
+    match format {
+        "tiff" => {
+            let layout = TiffLayout::parse(bytes)?;
+            let offsets = layout.ifd_offsets(bytes)?;
+            let offset = *offsets.get(page as usize).ok_or_else(out_of_range)?;
+            if page == 0 {
+                return Ok(Cow::Borrowed(bytes));
+            }
+            let (at, len) = layout.first_ifd_field();
+            let mut rewritten = bytes.to_vec();
+            layout.write(&mut rewritten, at, len, offset);
+            Ok(Cow::Owned(rewritten))
+        }
+        "ico" => {
+            let count = ico_entry_count(bytes)?;
+            if page as usize >= count {
+                return Err(out_of_range());
+            }
+            let entry = 6 + page as usize * 16;
+            let le32 = |at: usize| {
+                u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+            };
+            let size = le32(entry + 8) as usize;
+            let offset = le32(entry + 12) as usize;
+            let data = bytes
+                .get(offset..offset + size)
+                .ok_or_else(|| "ICO truncado".to_string())?;
+
+            let mut single = Vec::with_capacity(22 + size);
+            single.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+            single.extend_from_slice(&bytes[entry..entry + 12]);
+            single.extend_from_slice(&22u32.to_le_bytes());
+            single.extend_from_slice(data);
+            Ok(Cow::Owned(single))
+        }
+        _ if page == 0 => Ok(Cow::Borrowed(bytes)),
+        _ => Err(out_of_range()),
+    }
+}
+
+fn ico_entry_count(bytes: &[u8]) -> Result<usize, String> {
+    let header = bytes.get(..6).ok_or_else(|| "ICO invalido".to_string())?;
+    let count = u16::from_le_bytes([header[4], header[5]]) as usize;
+    if bytes.len() < 6 + count * 16 {
+        return Err("ICO truncado".to_string());
+    }
+    Ok(count)
+}
@@ -0,0 +1,140 @@
+use crate::models::{PaletteColor, PaletteResponse};
+use crate::utils::css_hex;
+use image::{DynamicImage, imageops::FilterType};
+
+// Palette from a downscaled copy; 100 px keeps it fast without losing small accents
+const PALETTE_SAMPLE_SIDE: u32 = 100;
+const MIN_ALPHA: u8 = 128;
+const KMEANS_ITERATIONS: usize = 4;
+
+pub const DEFAULT_PALETTE_COLORS: usize = 5;
+pub const MAX_PALETTE_COLORS: usize = 16;
+
+pub fn extract_palette(img: &DynamicImage, colors: usize) -> Result<PaletteResponse, String> {
+    let small = if img.width() > PALETTE_SAMPLE_SIDE || img.height() > PALETTE_SAMPLE_SIDE {
+        img.resize(
+            PALETTE_SAMPLE_SIDE,
+            PALETTE_SAMPLE_SIDE,
+            FilterType::Triangle,
+        )
+    } else {
+        img.clone()
+    };
+
+    let pixels: Vec<[u8; 3]> = small
+        .to_rgba8()
+        .pixels()
+        .filter(|p| p.0[3] >= MIN_ALPHA)
+        .map(|p| [p.0[0], p.0[1], p.0[2]])
+        .collect();
+    if pixels.is_empty() {
+        return Err("La imagen es completamente transparente".to_string());
+    }
+
+    let total = pixels.len() as f64;
+    let mut boxes = vec![pixels.clone()];
+
+    while boxes.len() < colors {
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| {
+                let (channel, range) = widest_channel(b);
+                (i, channel, range)
+            })
+            .filter(|(_, _, range)| *range > 0)
+            .max_by_key(|(_, _, range)| *range)
+            .map(|(i, channel, _)| (i, channel))
+        else {
+            break;
+        };
+
+        let mut color_box = boxes.swap_remove(index);
+        color_box.sort_unstable_by_key(|p| p[channel]);
+        let upper = color_box.split_off(color_box.len() / 2);
+        boxes.push(color_box);
+        boxes.push(upper);
+    }
+
+    // Median cut seeds the centroids; a few k-means passes then assign every pixel
+    // to its nearest colour so shares reflect real pixel counts
+    let mut centroids: Vec<[u8; 3]> = boxes.iter().map(|b| average(b)).collect();
+    let mut counts = vec![0usize; centroids.len()];
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut sums = vec![[0u64; 3]; centroids.len()];
+        counts = vec![0usize; centroids.len()];
+        for p in &pixels {
+            let nearest = nearest_centroid(&centroids, p);
+            counts[nearest] += 1;
+            for c in 0..3 {
+                sums[nearest][c] += p[c] as u64;
+            }
+        }
+        for (i, centroid) in centroids.iter_mut().enumerate() {
+            if counts[i] > 0 {
+                let n = counts[i] as u64;
+                *centroid = [
+                    (sums[i][0] / n) as u8,
+                    (sums[i][1] / n) as u8,
+                    (sums[i][2] / n) as u8,
+                ];
+            }
+        }
+    }
+
+    let mut palette: Vec<PaletteColor> = centroids
+        .iter()
+        .zip(&counts)
+        .filter(|(_, count)| **count > 0)
+        .map(|(centroid, count)| PaletteColor {
+            hex: css_hex(centroid),
+            share: *count as f64 / total,
+        })
+        .collect();
+    palette.sort_by(|a, b| b.share.total_cmp(&a.share));
+
+    Ok(PaletteResponse {
+        dominant: palette[0].clone(),
+        palette,
+    })
+}
+
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|c| {
+            let (min, max) = pixels.iter().fold((u8::MAX, u8::MIN), |(lo, hi), p| {
+                (lo.min(p[c]), hi.max(p[c]))
+            });
+            (c, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+fn nearest_centroid(centroids: &[[u8; 3]], pixel: &[u8; 3]) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, centroid)| {
+            (0..3)
+                .map(|c| {
+                    let d = centroid[c] as i32 - pixel[c] as i32;
+                    d * d
+                })
+                .sum::<i32>()
+        })
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+fn average(pixels: &[[u8; 3]]) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    for p in pixels {
+        for c in 0..3 {
+            sum[c] += p[c] as u64;
+        }
+    }
+    let n = pixels.len().max(1) as u64;
+    [(sum[0] / n) as u8, (sum[1] / n) as u8, (sum[2] / n) as u8]
+}
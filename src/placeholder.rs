@@ -0,0 +1,48 @@
+use crate::models::PlaceholderKind;
+use base64::{Engine as _, engine::general_purpose};
+use image::{DynamicImage, imageops::FilterType};
+
+// ThumbHash only accepts images up to 100x100; BlurHash cost grows with the pixel count
+const THUMBHASH_MAX_SIDE: u32 = 100;
+const BLURHASH_MAX_SIDE: u32 = 64;
+
+pub const DEFAULT_BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+
+pub fn compute_placeholder(
+    img: &DynamicImage,
+    kind: PlaceholderKind,
+    components: (u32, u32),
+) -> Result<String, String> {
+    let max_side = match kind {
+        PlaceholderKind::BlurHash => BLURHASH_MAX_SIDE,
+        PlaceholderKind::ThumbHash => THUMBHASH_MAX_SIDE,
+    };
+    let small = if img.width() > max_side || img.height() > max_side {
+        img.resize(max_side, max_side, FilterType::Triangle)
+    } else {
+        img.clone()
+    };
+    let rgba = small.to_rgba8();
+    let (width, height) = rgba.dimensions();
+
+    match kind {
+        PlaceholderKind::BlurHash => {
+            let (x, y) = components;
+            blurhash::encode(x, y, width, height, rgba.as_raw())
+                .map_err(|_| "Error generando BlurHash".to_string())
+        }
+        PlaceholderKind::ThumbHash => {
+            let hash =
+                thumbhash::rgba_to_thumb_hash(width as usize, height as usize, rgba.as_raw());
+            Ok(general_purpose::STANDARD.encode(hash))
+        }
+    }
+}
+
+pub fn parse_placeholder_kind(value: &str) -> Result<PlaceholderKind, String> {
+    match value.to_lowercase().as_str() {
+        "blurhash" => Ok(PlaceholderKind::BlurHash),
+        "thumbhash" => Ok(PlaceholderKind::ThumbHash),
+        _ => Err(format!("Tipo de placeholder invalido: {}", value)),
+    }
+}
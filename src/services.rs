@@ -1,12 +1,42 @@
+use crate::cache::result_cache;
+use crate::hashing::{hash_distances, perceptual_hashes};
+use crate::jxl::{decode_jxl, encode_jxl, is_jxl};
 use crate::models::*;
-use crate::utils::decode_base64;
+use crate::pages::{page_count, select_page};
+use crate::palette::extract_palette;
+use crate::placeholder::{
+    DEFAULT_BLURHASH_COMPONENTS, compute_placeholder, parse_placeholder_kind,
+};
+use crate::svg::{is_svg, load_svg, render_svg, svg_scale};
+use crate::text::draw_text_overlay;
+use crate::tonemap::{is_float, is_high_precision, parse_tone_map, tone_map};
+use crate::utils::{decode_base64, linear_to_srgb, quality_for_dpr, srgb_to_linear, to_hex};
 use base64::{Engine as _, engine::general_purpose};
+use futures_util::{StreamExt, stream};
 use image::{
-    DynamicImage, GenericImageView, ImageEncoder, ImageFormat, Rgba, RgbaImage,
-    codecs::jpeg::JpegEncoder, codecs::png::PngEncoder,
+    ColorType, DynamicImage, ExtendedColorType, GenericImageView, ImageDecoder, ImageEncoder,
+    ImageFormat, ImageReader, Rgba, Rgba32FImage, RgbaImage,
+    codecs::avif::AvifEncoder,
+    codecs::ico::{IcoEncoder, IcoFrame},
+    codecs::jpeg::JpegEncoder,
+    codecs::png::PngEncoder,
+    imageops,
+    imageops::FilterType,
+    metadata::Orientation,
 };
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
 use std::io::Cursor;
 
+const LQIP_WIDTH: u32 = 20;
+const LQIP_QUALITY: u8 = 20;
+
+const FAVICON_SIZES: [u32; 3] = [16, 32, 48];
+const APPLE_TOUCH_SIZES: [u32; 4] = [180, 167, 152, 120];
+const ANDROID_SIZES: [u32; 2] = [192, 512];
+// Maskable icons keep the logo inside the central 80% safe zone
+const MASKABLE_SAFE_ZONE: f32 = 0.8;
+
 pub struct ImageCompressionService;
 
 impl ImageCompressionService {
@@ -22,30 +52,65 @@ impl ImageCompressionService {
         let image_data = decode_base64(&request.image_data)
             .map_err(|_| "Datos de imagen base64 inválidos".to_string())?;
 
-        if image_data.bytes.len() > max_image_size {
+        self.optimize_raw_image(&image_data.bytes, &request, max_image_size)
+    }
+
+    pub fn optimize_raw_image(
+        &self,
+        original_bytes: &[u8],
+        request: &OptimizeRequest,
+        max_image_size: usize,
+    ) -> Result<CompressionResult, String> {
+        if original_bytes.len() > max_image_size {
             return Err("Payload demasiado grande".to_string());
         }
 
-        let original_format = self.detect_image_format(&image_data.bytes)?;
+        let original_format = self.detect_image_format(original_bytes)?;
         let output_format = self.determine_output_format(&request.format, &original_format);
 
-        let img = image::load_from_memory(&image_data.bytes)
-            .map_err(|_| "Formato de imagen no soportado".to_string())?;
+        if !(1.0..=4.0).contains(&request.dpr) {
+            return Err("Parametro dpr invalido (1-4)".to_string());
+        }
+
+        if !(-10.0..=10.0).contains(&request.exposure) {
+            return Err("Parametro exposure invalido (-10 a 10)".to_string());
+        }
+        let operator = request.tonemap.as_deref().map(parse_tone_map).transpose()?;
 
+        let img = self.decode_image(original_bytes, &original_format, None, request.dpr)?;
+        let img = self.to_display_range(img, operator, request.exposure, output_format == "png");
+
+        let quality = request
+            .quality
+            .unwrap_or_else(|| quality_for_dpr(default_quality(), request.dpr));
         let effective_quality = if request.aggressive {
-            request.quality.min(60)
+            quality.min(60)
         } else {
-            request.quality
+            quality
         };
 
         let result_bytes = match output_format.as_str() {
             "jpeg" => self.compress_jpeg_with_quality(&img, effective_quality)?,
-            "png" => self.compress_png(&image_data.bytes)?,
+            "png" => self.compress_png_from_image(&img)?,
             "webp" => self.compress_webp_with_quality(&img, effective_quality)?,
             _ => return Err("Formato de salida no soportado".to_string()),
         };
 
-        let original_size = image_data.bytes.len();
+        let placeholder = match request.placeholder.as_deref() {
+            Some(kind) => Some(compute_placeholder(
+                &img,
+                parse_placeholder_kind(kind)?,
+                DEFAULT_BLURHASH_COMPONENTS,
+            )?),
+            None => None,
+        };
+        let lqip = if request.lqip {
+            Some(self.lqip_data_uri(&img, &output_format)?)
+        } else {
+            None
+        };
+
+        let original_size = original_bytes.len();
         let optimized_size = result_bytes.len();
         let compression_ratio =
             ((original_size as f64 - optimized_size as f64) / original_size as f64) * 100.0;
@@ -58,18 +123,364 @@ impl ImageCompressionService {
             original_format,
             output_format,
             quality_used: effective_quality,
+            placeholder,
+            lqip,
         })
     }
 
+    // Decoding, encoding and the disk cache all block, so they run off the async runtime
     pub async fn optimize_image_bytes(
         &self,
-        original_bytes: &[u8],
-        options: &TransformOptions,
+        original_bytes: Vec<u8>,
+        options: TransformOptions,
     ) -> Result<BinaryCompressionResult, String> {
-        self.process_image_bytes(original_bytes, options)
+        tokio::task::spawn_blocking(move || {
+            ImageCompressionService::new().process_image_bytes(&original_bytes, &options)
+        })
+        .await
+        .unwrap_or_else(|_| Err("Error procesando imagen".to_string()))
+    }
+
+    pub async fn generate_placeholder(
+        &self,
+        original_bytes: &[u8],
+        kind: PlaceholderKind,
+        components: (u32, u32),
+    ) -> Result<PlaceholderResponse, String> {
+        let original_bytes = original_bytes.to_vec();
+        run_blocking(move |service| {
+            let format = service.detect_image_format(&original_bytes)?;
+            let img = service.decode_image(&original_bytes, &format, None, 1.0)?;
+            let img = service.to_display_range(img, None, 0.0, false);
+
+            Ok(PlaceholderResponse {
+                placeholder: compute_placeholder(&img, kind, components)?,
+                kind,
+                width: img.width(),
+                height: img.height(),
+            })
+        })
+        .await
+    }
+
+    pub async fn generate_palette(
+        &self,
+        original_bytes: &[u8],
+        colors: usize,
+    ) -> Result<PaletteResponse, String> {
+        let original_bytes = original_bytes.to_vec();
+        run_blocking(move |service| {
+            let format = service.detect_image_format(&original_bytes)?;
+            let img = service.decode_image(&original_bytes, &format, None, 1.0)?;
+
+            extract_palette(&service.to_display_range(img, None, 0.0, false), colors)
+        })
+        .await
+    }
+
+    pub async fn image_info(
+        &self,
+        original_bytes: &[u8],
+        palette_colors: Option<usize>,
+        page: Option<u32>,
+    ) -> Result<InfoResponse, String> {
+        let original_bytes = original_bytes.to_vec();
+        run_blocking(move |service| {
+            let format = service.detect_image_format(&original_bytes)?;
+            let pages = page_count(&original_bytes, &format)?;
+            let source = match page {
+                Some(page) => select_page(&original_bytes, &format, page)?,
+                None => Cow::Borrowed(original_bytes.as_slice()),
+            };
+            let img = service.decode_image(&source, &format, None, 1.0)?;
+
+            let palette = match palette_colors {
+                Some(colors) => Some(extract_palette(
+                    &service.to_display_range(img.clone(), None, 0.0, false),
+                    colors,
+                )?),
+                None => None,
+            };
+
+            Ok(InfoResponse {
+                format,
+                width: img.width(),
+                height: img.height(),
+                size: original_bytes.len(),
+                color_type: format!("{:?}", img.color()).to_lowercase(),
+                has_alpha: img.color().has_alpha(),
+                pages,
+                palette,
+            })
+        })
+        .await
+    }
+
+    pub async fn perceptual_hash(&self, original_bytes: &[u8]) -> Result<ImageHashes, String> {
+        let original_bytes = original_bytes.to_vec();
+        run_blocking(move |service| {
+            let img = service.decode_oriented(&original_bytes)?;
+            Ok(perceptual_hashes(&img))
+        })
+        .await
+    }
+
+    pub async fn compare_images(
+        &self,
+        first: &[u8],
+        second: &[u8],
+        threshold: u32,
+    ) -> Result<SimilarResponse, String> {
+        let (first, second) = (first.to_vec(), second.to_vec());
+        run_blocking(move |service| {
+            let hashes = vec![
+                perceptual_hashes(&service.decode_oriented(&first)?),
+                perceptual_hashes(&service.decode_oriented(&second)?),
+            ];
+            let distance = hash_distances(&hashes[0], &hashes[1])?;
+
+            Ok(SimilarResponse {
+                similar: distance.phash <= threshold,
+                hashes,
+                distance,
+                threshold,
+            })
+        })
+        .await
+    }
+
+    // Returns (filename, PNG/ICO bytes); apple-touch and maskable icons are opaque
+    // because iOS and Android masks show transparency as black
+    pub async fn generate_icons(
+        &self,
+        original_bytes: &[u8],
+        background: [u8; 4],
+    ) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let original_bytes = original_bytes.to_vec();
+        run_blocking(move |service| service.build_icons(&original_bytes, background)).await
+    }
+
+    fn build_icons(
+        &self,
+        original_bytes: &[u8],
+        background: [u8; 4],
+    ) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let format = self.detect_image_format(original_bytes)?;
+        let source = if format == "svg" {
+            let largest = ANDROID_SIZES[ANDROID_SIZES.len() - 1];
+            self.decode_image(
+                original_bytes,
+                &format,
+                Some(&self.icon_resize_options(largest)),
+                1.0,
+            )?
+        } else {
+            self.decode_oriented(original_bytes)?
+        };
+        let logo = self.square_canvas(&source);
+        let mut files = Vec::new();
+
+        let mut frames = Vec::new();
+        for size in FAVICON_SIZES {
+            let png = self.encode_with_format(&self.icon_at(&logo, size)?, ImageFormat::Png)?;
+            frames.push((size, png));
+        }
+        for (size, png) in &frames[..2] {
+            files.push((format!("favicon-{}x{}.png", size, size), png.clone()));
+        }
+        let ico_frames = frames
+            .iter()
+            .map(|(size, png)| {
+                IcoFrame::with_encoded(png.as_slice(), *size, *size, ExtendedColorType::Rgba8)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "Error codificando ICO".to_string())?;
+        let mut ico = Vec::new();
+        IcoEncoder::new(&mut ico)
+            .encode_images(&ico_frames)
+            .map_err(|_| "Error codificando ICO".to_string())?;
+        files.push(("favicon.ico".to_string(), ico));
+
+        for size in APPLE_TOUCH_SIZES {
+            let icon = self.padded_icon(&logo, size, 1.0, background)?;
+            let name = if size == APPLE_TOUCH_SIZES[0] {
+                "apple-touch-icon.png".to_string()
+            } else {
+                format!("apple-touch-icon-{}x{}.png", size, size)
+            };
+            files.push((name, self.encode_with_format(&icon, ImageFormat::Png)?));
+        }
+
+        for size in ANDROID_SIZES {
+            let icon = self.icon_at(&logo, size)?;
+            files.push((
+                format!("android-chrome-{}x{}.png", size, size),
+                self.encode_with_format(&icon, ImageFormat::Png)?,
+            ));
+            let maskable = self.padded_icon(&logo, size, MASKABLE_SAFE_ZONE, background)?;
+            files.push((
+                format!("maskable-{}x{}.png", size, size),
+                self.encode_with_format(&maskable, ImageFormat::Png)?,
+            ));
+        }
+
+        Ok(files)
+    }
+
+    // Centers a non-square logo on a transparent square so icons never crop it
+    fn square_canvas(&self, img: &DynamicImage) -> DynamicImage {
+        let (width, height) = img.dimensions();
+        let side = width.max(height);
+        if width == height {
+            return img.clone();
+        }
+        let mut canvas = RgbaImage::new(side, side);
+        imageops::overlay(
+            &mut canvas,
+            &img.to_rgba8(),
+            ((side - width) / 2) as i64,
+            ((side - height) / 2) as i64,
+        );
+        DynamicImage::ImageRgba8(canvas)
+    }
+
+    fn icon_resize_options(&self, size: u32) -> ResizeOptions {
+        ResizeOptions {
+            width: Some(size),
+            height: Some(size),
+            mode: ResizeMode::Fit,
+            filter: ResizeFilter::Lanczos3,
+            enlarge: true,
+            linear: None,
+        }
+    }
+
+    fn icon_at(&self, logo: &DynamicImage, size: u32) -> Result<DynamicImage, String> {
+        let icon = self.resize_image(logo, &self.icon_resize_options(size), false)?;
+        Ok(DynamicImage::ImageRgba8(icon.to_rgba8()))
+    }
+
+    fn padded_icon(
+        &self,
+        logo: &DynamicImage,
+        size: u32,
+        scale: f32,
+        background: [u8; 4],
+    ) -> Result<DynamicImage, String> {
+        let inner = ((size as f32 * scale).round() as u32).max(1);
+        let icon = self.icon_at(logo, inner)?;
+        let mut canvas = RgbaImage::from_pixel(size, size, Rgba(background));
+        let offset = ((size - icon.width()) / 2) as i64;
+        imageops::overlay(&mut canvas, &icon.to_rgba8(), offset, offset);
+        Ok(DynamicImage::ImageRgba8(canvas))
+    }
+
+    // SVG is rasterised at the size the resize step will ask for (or intrinsic x dpr),
+    // so vector sources never get upscaled from a small raster
+    fn decode_image(
+        &self,
+        bytes: &[u8],
+        format: &str,
+        resize: Option<&ResizeOptions>,
+        dpr: f32,
+    ) -> Result<DynamicImage, String> {
+        if format == "svg" {
+            let tree = load_svg(bytes)?;
+            return render_svg(&tree, svg_scale(&tree, resize, dpr));
+        }
+        if format == "jxl" {
+            return decode_jxl(bytes);
+        }
+        image::load_from_memory(bytes).map_err(|_| "Formato de imagen no soportado".to_string())
+    }
+
+    // Applies the EXIF orientation so a rotated copy of a photo hashes the same
+    fn decode_oriented(&self, bytes: &[u8]) -> Result<DynamicImage, String> {
+        let format = self.detect_image_format(bytes)?;
+        if format == "svg" || format == "jxl" {
+            return self.decode_image(bytes, &format, None, 1.0);
+        }
+        let mut decoder = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .map_err(|_| "Formato de imagen no soportado".to_string())?
+            .into_decoder()
+            .map_err(|_| "Formato de imagen no soportado".to_string())?;
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+        let mut img = DynamicImage::from_decoder(decoder)
+            .map_err(|_| "Formato de imagen no soportado".to_string())?;
+        img.apply_orientation(orientation);
+
+        Ok(self.to_display_range(img, None, 0.0, false))
+    }
+
+    // HDR (float, scene-linear) sources are always tone mapped; 16-bit ones only when an
+    // operator or exposure is given, otherwise they are just narrowed for 8-bit outputs
+    fn to_display_range(
+        &self,
+        img: DynamicImage,
+        operator: Option<ToneMap>,
+        exposure: f32,
+        keep_16bit: bool,
+    ) -> DynamicImage {
+        if !is_high_precision(&img) {
+            return img;
+        }
+        if is_float(&img) || operator.is_some() || exposure != 0.0 {
+            return tone_map(
+                &img,
+                operator.unwrap_or(ToneMap::Aces),
+                exposure,
+                keep_16bit,
+            );
+        }
+        match (keep_16bit, img.color().has_alpha()) {
+            (true, _) => img,
+            (false, true) => DynamicImage::ImageRgba8(img.to_rgba8()),
+            (false, false) => DynamicImage::ImageRgb8(img.to_rgb8()),
+        }
+    }
+
+    pub async fn generate_variants(
+        &self,
+        original_bytes: Vec<u8>,
+        variants: Vec<TransformOptions>,
+    ) -> Result<Vec<BinaryCompressionResult>, String> {
+        tokio::task::spawn_blocking(move || {
+            let service = ImageCompressionService::new();
+            variants
+                .iter()
+                .map(|options| service.process_image_bytes(&original_bytes, options))
+                .collect()
+        })
+        .await
+        .unwrap_or_else(|_| Err("Error procesando imagen".to_string()))
+    }
+
+    pub async fn process_batch(
+        &self,
+        jobs: Vec<(Vec<u8>, TransformOptions)>,
+        concurrency: usize,
+    ) -> Vec<Result<BinaryCompressionResult, String>> {
+        stream::iter(jobs)
+            .map(|(bytes, options)| async move {
+                tokio::task::spawn_blocking(move || {
+                    ImageCompressionService::new().process_image_bytes(&bytes, &options)
+                })
+                .await
+                .unwrap_or_else(|_| Err("Error procesando imagen".to_string()))
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
     }
 
     fn detect_image_format(&self, bytes: &[u8]) -> Result<String, String> {
+        if is_svg(bytes) {
+            return Ok("svg".to_string());
+        }
+        if is_jxl(bytes) {
+            return Ok("jxl".to_string());
+        }
         match image::guess_format(bytes) {
             Ok(ImageFormat::Jpeg) => Ok("jpeg".to_string()),
             Ok(ImageFormat::Png) => Ok("png".to_string()),
@@ -77,6 +488,9 @@ impl ImageCompressionService {
             Ok(ImageFormat::Gif) => Ok("gif".to_string()),
             Ok(ImageFormat::Bmp) => Ok("bmp".to_string()),
             Ok(ImageFormat::Tiff) => Ok("tiff".to_string()),
+            Ok(ImageFormat::Hdr) => Ok("hdr".to_string()),
+            Ok(ImageFormat::OpenExr) => Ok("exr".to_string()),
+            Ok(ImageFormat::Qoi) => Ok("qoi".to_string()),
             Ok(format) => Ok(format!("{:?}", format).to_lowercase()),
             Err(_) => Err("No se pudo detectar el formato de imagen".to_string()),
         }
@@ -85,6 +499,7 @@ impl ImageCompressionService {
     fn determine_output_format(&self, requested: &str, original: &str) -> String {
         match requested {
             "auto" => match original {
+                "svg" | "hdr" | "exr" => "png".to_string(),
                 "png" => "jpeg".to_string(),
                 "jpeg" => "jpeg".to_string(),
                 _ => "jpeg".to_string(),
@@ -117,27 +532,6 @@ impl ImageCompressionService {
         Ok(buffer)
     }
 
-    fn compress_png(&self, original_bytes: &[u8]) -> Result<Vec<u8>, String> {
-        let img = image::load_from_memory(original_bytes)
-            .map_err(|_| "Error cargando PNG".to_string())?;
-
-        let mut buffer = Vec::new();
-        let mut cursor = Cursor::new(&mut buffer);
-
-        let encoder = PngEncoder::new(&mut cursor);
-
-        encoder
-            .write_image(
-                img.as_bytes(),
-                img.width(),
-                img.height(),
-                img.color().into(),
-            )
-            .map_err(|_| "Error comprimiendo PNG".to_string())?;
-
-        Ok(buffer)
-    }
-
     fn compress_png_from_image(&self, img: &DynamicImage) -> Result<Vec<u8>, String> {
         let mut buffer = Vec::new();
         let mut cursor = Cursor::new(&mut buffer);
@@ -169,28 +563,89 @@ impl ImageCompressionService {
         Ok(encoded.to_vec())
     }
 
+    fn compress_avif_with_quality(
+        &self,
+        img: &DynamicImage,
+        quality: u8,
+    ) -> Result<Vec<u8>, String> {
+        let mut buffer = Vec::new();
+        let encoder = AvifEncoder::new_with_speed_quality(&mut buffer, 8, quality);
+        let result = if img.color().has_alpha() {
+            DynamicImage::ImageRgba8(img.to_rgba8()).write_with_encoder(encoder)
+        } else {
+            DynamicImage::ImageRgb8(img.to_rgb8()).write_with_encoder(encoder)
+        };
+        result.map_err(|_| "Error codificando AVIF".to_string())?;
+
+        Ok(buffer)
+    }
+
+    // QOI only stores 8-bit RGB/RGBA
+    fn compress_qoi(&self, img: &DynamicImage) -> Result<Vec<u8>, String> {
+        let img = if img.color().has_alpha() {
+            DynamicImage::ImageRgba8(img.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(img.to_rgb8())
+        };
+        self.encode_with_format(&img, ImageFormat::Qoi)
+    }
+
     fn process_image_bytes(
         &self,
         original_bytes: &[u8],
         options: &TransformOptions,
     ) -> Result<BinaryCompressionResult, String> {
         let original_format = self.detect_image_format(original_bytes)?;
-        let mut output_format = options
-            .output_format
-            .clone()
-            .unwrap_or_else(|| original_format.clone());
+        let etag = self.etag_for(original_bytes, options);
+        let cache_key = etag.trim_matches('"').to_string();
 
-        let mut img = image::load_from_memory(original_bytes)
-            .map_err(|_| "Formato de imagen no soportado".to_string())?;
+        if let Some(cached) = result_cache().and_then(|cache| cache.get(&cache_key, &etag)) {
+            return Ok(cached);
+        }
+        let mut output_format =
+            options
+                .output_format
+                .clone()
+                .unwrap_or_else(|| match original_format.as_str() {
+                    "svg" | "ico" | "hdr" | "exr" => "png".to_string(),
+                    format => format.to_string(),
+                });
+
+        let source = match options.page {
+            Some(page) => select_page(original_bytes, &original_format, page)?,
+            None => Cow::Borrowed(original_bytes),
+        };
+        let mut img = self.decode_image(
+            &source,
+            &original_format,
+            options.resize.as_ref(),
+            options.dpr,
+        )?;
+
+        if let Some(tolerance) = options.trim {
+            img = self.trim_borders(&img, tolerance);
+        }
 
         if let Some(resize) = &options.resize {
-            img = self.resize_image(&img, resize)?;
+            let linear = resize.linear.unwrap_or(original_format == "jpeg");
+            img = self.resize_image(&img, resize, linear)?;
         }
 
+        img = self.to_display_range(
+            img,
+            options.tone_map,
+            options.exposure,
+            matches!(output_format.as_str(), "png" | "tiff" | "jxl"),
+        );
+
         if options.black_and_white {
             img = self.apply_black_and_white(&img);
         }
 
+        if let Some(text) = &options.text {
+            img = self.apply_text_overlay(&img, text)?;
+        }
+
         if options.border_radius > 0 {
             img = self.apply_border_radius(&img, options.border_radius);
             // Transparency requires a format that supports alpha
@@ -203,28 +658,80 @@ impl ImageCompressionService {
             "jpeg" => self.compress_jpeg_with_quality(&img, options.quality)?,
             "png" => self.compress_png_from_image(&img)?,
             "webp" => self.compress_webp_with_quality(&img, options.quality)?,
+            "avif" => self.compress_avif_with_quality(&img, options.quality)?,
             "gif" => self.encode_with_format(&img, ImageFormat::Gif)?,
             "bmp" => self.encode_with_format(&img, ImageFormat::Bmp)?,
             "tiff" => self.encode_with_format(&img, ImageFormat::Tiff)?,
+            "jxl" => encode_jxl(&img)?,
+            "qoi" => self.compress_qoi(&img)?,
             _ => return Err("Formato de salida no soportado".to_string()),
         };
 
+        let lqip = if options.lqip {
+            Some(self.lqip_data_uri(&img, &output_format)?)
+        } else {
+            None
+        };
+
         let original_size = original_bytes.len();
         let optimized_size = result_bytes.len();
+        let (width, height) = img.dimensions();
 
-        Ok(BinaryCompressionResult {
+        let result = BinaryCompressionResult {
             optimized_bytes: result_bytes,
             original_size,
             optimized_size,
             original_format,
             output_format,
-        })
+            width,
+            height,
+            etag,
+            last_modified: None,
+            lqip,
+        };
+
+        if let Some(cache) = result_cache() {
+            cache.put(&cache_key, &result);
+        }
+
+        Ok(result)
+    }
+
+    // ~20 px preview; JPEG outputs get a JPEG preview since the client may not support WebP
+    fn lqip_data_uri(&self, img: &DynamicImage, output_format: &str) -> Result<String, String> {
+        let small = img.resize(LQIP_WIDTH, u32::MAX, FilterType::Triangle);
+        let (mime, bytes) = if output_format == "jpeg" {
+            (
+                "image/jpeg",
+                self.compress_jpeg_with_quality(&small, LQIP_QUALITY)?,
+            )
+        } else {
+            (
+                "image/webp",
+                self.compress_webp_with_quality(&small, LQIP_QUALITY)?,
+            )
+        };
+
+        Ok(format!(
+            "data:{};base64,{}",
+            mime,
+            general_purpose::STANDARD.encode(bytes)
+        ))
+    }
+
+    // Same source and options always produce the same bytes, so the ETag is known before encoding
+    pub fn etag_for(&self, original_bytes: &[u8], options: &TransformOptions) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(Sha256::digest(original_bytes));
+        hasher.update(serde_json::to_vec(options).unwrap_or_default());
+        format!("\"{}\"", to_hex(&hasher.finalize()[..16]))
     }
 
     fn resize_image(
         &self,
         img: &DynamicImage,
         options: &ResizeOptions,
+        linear: bool,
     ) -> Result<DynamicImage, String> {
         let (orig_w, orig_h) = img.dimensions();
 
@@ -249,36 +756,183 @@ impl ImageCompressionService {
             return Err("Dimensiones invalidas".to_string());
         }
 
+        let filter = self.filter_type(options.filter);
+
         match options.mode {
             ResizeMode::Force => {
-                Ok(img.resize_exact(target_w, target_h, image::imageops::FilterType::Lanczos3))
+                let (target_w, target_h) = if options.enlarge {
+                    (target_w, target_h)
+                } else {
+                    (target_w.min(orig_w), target_h.min(orig_h))
+                };
+                Ok(self.resample(img, target_w, target_h, filter, linear))
             }
             ResizeMode::Fit => {
-                let resized = img.resize(target_w, target_h, image::imageops::FilterType::Lanczos3);
-                Ok(resized)
+                let fits_already = target_w >= orig_w && target_h >= orig_h;
+                if !options.enlarge && fits_already {
+                    return Ok(img.clone());
+                }
+                let scale = f32::min(
+                    target_w as f32 / orig_w as f32,
+                    target_h as f32 / orig_h as f32,
+                );
+                let fit_w = ((orig_w as f32 * scale).round() as u32).max(1);
+                let fit_h = ((orig_h as f32 * scale).round() as u32).max(1);
+                Ok(self.resample(img, fit_w, fit_h, filter, linear))
             }
             ResizeMode::Fill => {
-                let scale = f32::max(
+                let mut scale = f32::max(
                     target_w as f32 / orig_w as f32,
                     target_h as f32 / orig_h as f32,
                 );
+                let (mut target_w, mut target_h) = (target_w, target_h);
+
+                // Without enlarging, shrink the crop box (keeping its aspect) to the source size
+                if !options.enlarge && scale > 1.0 {
+                    target_w = ((target_w as f32 / scale).round() as u32).clamp(1, orig_w);
+                    target_h = ((target_h as f32 / scale).round() as u32).clamp(1, orig_h);
+                    scale = 1.0;
+                }
+
                 let scaled_w = (orig_w as f32 * scale).round() as u32;
                 let scaled_h = (orig_h as f32 * scale).round() as u32;
-                let resized = img.resize_exact(
-                    scaled_w.max(1),
-                    scaled_h.max(1),
-                    image::imageops::FilterType::Lanczos3,
-                );
+                let resized = if scale == 1.0 {
+                    img.clone()
+                } else {
+                    self.resample(img, scaled_w.max(1), scaled_h.max(1), filter, linear)
+                };
 
                 let x = (scaled_w.saturating_sub(target_w)) / 2;
                 let y = (scaled_h.saturating_sub(target_h)) / 2;
-                let cropped =
-                    image::imageops::crop_imm(&resized, x, y, target_w, target_h).to_image();
-                Ok(DynamicImage::ImageRgba8(cropped))
+                Ok(resized.crop_imm(x, y, target_w, target_h))
             }
         }
     }
 
+    fn resample(
+        &self,
+        img: &DynamicImage,
+        width: u32,
+        height: u32,
+        filter: FilterType,
+        linear: bool,
+    ) -> DynamicImage {
+        if !linear {
+            return img.resize_exact(width, height, filter);
+        }
+
+        let linear_img = self.linearize(img);
+        let resized = image::imageops::resize(&linear_img, width, height, filter);
+        self.delinearize(resized, img.color())
+    }
+
+    // Converts to premultiplied linear-light RGBA. Float inputs are assumed to be linear already.
+    fn linearize(&self, img: &DynamicImage) -> Rgba32FImage {
+        let is_float = matches!(img.color(), ColorType::Rgb32F | ColorType::Rgba32F);
+        let mut rgba = img.to_rgba32f();
+
+        for pixel in rgba.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let [r, g, b] = if is_float {
+                [r, g, b]
+            } else {
+                [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)]
+            };
+            *pixel = Rgba([r * a, g * a, b * a, a]);
+        }
+
+        rgba
+    }
+
+    fn delinearize(&self, mut rgba: Rgba32FImage, color: ColorType) -> DynamicImage {
+        let is_float = matches!(color, ColorType::Rgb32F | ColorType::Rgba32F);
+
+        for pixel in rgba.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let a = a.clamp(0.0, 1.0);
+            let unpremultiply = |c: f32| if a > 0.0 { c / a } else { 0.0 };
+            let [r, g, b] = [unpremultiply(r), unpremultiply(g), unpremultiply(b)];
+            *pixel = if is_float {
+                Rgba([r.max(0.0), g.max(0.0), b.max(0.0), a])
+            } else {
+                Rgba([linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a])
+            };
+        }
+
+        let rgba = DynamicImage::ImageRgba32F(rgba);
+        match color {
+            ColorType::L8 => DynamicImage::ImageLuma8(rgba.to_luma8()),
+            ColorType::La8 => DynamicImage::ImageLumaA8(rgba.to_luma_alpha8()),
+            ColorType::Rgb8 => DynamicImage::ImageRgb8(rgba.to_rgb8()),
+            ColorType::L16 => DynamicImage::ImageLuma16(rgba.to_luma16()),
+            ColorType::La16 => DynamicImage::ImageLumaA16(rgba.to_luma_alpha16()),
+            ColorType::Rgb16 => DynamicImage::ImageRgb16(rgba.to_rgb16()),
+            ColorType::Rgba16 => DynamicImage::ImageRgba16(rgba.to_rgba16()),
+            ColorType::Rgb32F => DynamicImage::ImageRgb32F(rgba.to_rgb32f()),
+            ColorType::Rgba32F => rgba,
+            _ => DynamicImage::ImageRgba8(rgba.to_rgba8()),
+        }
+    }
+
+    fn filter_type(&self, filter: ResizeFilter) -> FilterType {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+
+    fn trim_borders(&self, img: &DynamicImage, tolerance: u8) -> DynamicImage {
+        let rgba = img.to_rgba8();
+        let (width, height) = rgba.dimensions();
+        if width == 0 || height == 0 {
+            return img.clone();
+        }
+
+        let reference = *rgba.get_pixel(0, 0);
+        let is_border = |x: u32, y: u32| {
+            let pixel = rgba.get_pixel(x, y);
+            if reference.0[3] == 0 && pixel.0[3] == 0 {
+                return true;
+            }
+            pixel
+                .0
+                .iter()
+                .zip(reference.0.iter())
+                .all(|(a, b)| a.abs_diff(*b) <= tolerance)
+        };
+        let row_is_border = |y: u32| (0..width).all(|x| is_border(x, y));
+        let column_is_border =
+            |x: u32, top: u32, bottom: u32| (top..bottom).all(|y| is_border(x, y));
+
+        let top = match (0..height).find(|&y| !row_is_border(y)) {
+            Some(top) => top,
+            // Uniform image: nothing meaningful to keep, leave it untouched
+            None => return img.clone(),
+        };
+        let bottom = (top..height)
+            .rev()
+            .find(|&y| !row_is_border(y))
+            .unwrap_or(top)
+            + 1;
+        let left = (0..width)
+            .find(|&x| !column_is_border(x, top, bottom))
+            .unwrap_or(0);
+        let right = (left..width)
+            .rev()
+            .find(|&x| !column_is_border(x, top, bottom))
+            .unwrap_or(left)
+            + 1;
+
+        if left == 0 && top == 0 && right == width && bottom == height {
+            return img.clone();
+        }
+
+        img.crop_imm(left, top, right - left, bottom - top)
+    }
+
     fn apply_black_and_white(&self, img: &DynamicImage) -> DynamicImage {
         let mut rgba = img.to_rgba8();
         for pixel in rgba.pixels_mut() {
@@ -289,6 +943,16 @@ impl ImageCompressionService {
         DynamicImage::ImageRgba8(rgba)
     }
 
+    fn apply_text_overlay(
+        &self,
+        img: &DynamicImage,
+        overlay: &TextOverlay,
+    ) -> Result<DynamicImage, String> {
+        let mut rgba = img.to_rgba8();
+        draw_text_overlay(&mut rgba, overlay)?;
+        Ok(DynamicImage::ImageRgba8(rgba))
+    }
+
     fn apply_border_radius(&self, img: &DynamicImage, radius: u32) -> DynamicImage {
         let mut rgba = img.to_rgba8();
         let (width, height) = rgba.dimensions();
@@ -362,6 +1026,19 @@ impl ImageCompressionService {
             original_format: result.original_format,
             output_format: result.output_format,
             quality_used: result.quality_used,
+            placeholder: result.placeholder,
+            lqip: result.lqip,
         }
     }
 }
+
+// Decoding, hashing and encoding block, so they run on tokio's blocking pool
+async fn run_blocking<T, F>(job: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(ImageCompressionService) -> Result<T, String> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || job(ImageCompressionService::new()))
+        .await
+        .unwrap_or_else(|_| Err("Error procesando imagen".to_string()))
+}
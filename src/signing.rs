@@ -0,0 +1,177 @@
+use crate::config::AppConfig;
+use crate::models::HttpError;
+use base64::{Engine as _, engine::general_purpose};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// The signed message is the path after `/img` plus `?exp=<ts>` when the URL expires
+fn signing_message(path: &str, expires: Option<u64>) -> String {
+    match expires {
+        Some(exp) => format!("{}?exp={}", path, exp),
+        None => path.to_string(),
+    }
+}
+
+fn mac_for(key: &str, path: &str, expires: Option<u64>) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC acepta cualquier clave");
+    mac.update(signing_message(path, expires).as_bytes());
+    mac
+}
+
+pub fn sign_path(key: &str, path: &str, expires: Option<u64>) -> String {
+    let signature = mac_for(key, path, expires).finalize().into_bytes();
+    general_purpose::URL_SAFE_NO_PAD.encode(signature)
+}
+
+pub fn verify_signature(
+    key: &str,
+    path: &str,
+    expires: Option<u64>,
+    signature: Option<&str>,
+) -> Result<(), HttpError> {
+    let signature = signature.ok_or_else(|| HttpError::new(403, "Firma requerida"))?;
+    let signature = general_purpose::URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| HttpError::new(403, "Firma invalida"))?;
+
+    mac_for(key, path, expires)
+        .verify_slice(&signature)
+        .map_err(|_| HttpError::new(403, "Firma invalida"))?;
+
+    if let Some(exp) = expires
+        && unix_now() > exp
+    {
+        return Err(HttpError::new(403, "URL expirada"));
+    }
+
+    Ok(())
+}
+
+pub fn signed_url(key: &str, path: &str, expires: Option<u64>) -> String {
+    let signature = sign_path(key, path, expires);
+    match expires {
+        Some(exp) => format!("/img{}?exp={}&sig={}", path, exp, signature),
+        None => format!("/img{}?sig={}", path, signature),
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// `service-compress-image sign <path> [--ttl <seconds>] [--base <url>]`
+pub fn run_cli(config: &AppConfig, args: &[String]) -> Result<(), String> {
+    let key = config
+        .signing
+        .key
+        .as_deref()
+        .ok_or_else(|| "SIGNING_KEY no configurado".to_string())?;
+
+    let mut path = None;
+    let mut ttl = None;
+    let mut base = String::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ttl" => {
+                ttl = Some(
+                    args.next()
+                        .and_then(|v| v.parse::<u64>().ok())
+                        .ok_or_else(|| "--ttl requiere segundos".to_string())?,
+                )
+            }
+            "--base" => {
+                base = args
+                    .next()
+                    .ok_or_else(|| "--base requiere una URL".to_string())?
+                    .trim_end_matches('/')
+                    .to_string()
+            }
+            value => path = Some(value.to_string()),
+        }
+    }
+
+    let path = path.ok_or_else(|| {
+        "Uso: service-compress-image sign /img/{options}/{source} [--ttl <segundos>] [--base <url>]"
+            .to_string()
+    })?;
+    let path = path.strip_prefix("/img").unwrap_or(&path);
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    };
+
+    let expires = ttl.map(|ttl| unix_now() + ttl);
+    println!("{}{}", base, signed_url(key, &path, expires));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "test-key";
+    const PATH: &str = "/w:300,f:webp/products/1.jpg";
+
+    fn status(result: Result<(), HttpError>) -> u16 {
+        result.map(|_| 200).unwrap_or_else(|e| e.status)
+    }
+
+    #[test]
+    fn accepts_valid_signatures() {
+        let sig = sign_path(KEY, PATH, None);
+        assert!(verify_signature(KEY, PATH, None, Some(&sig)).is_ok());
+
+        let exp = unix_now() + 60;
+        let sig = sign_path(KEY, PATH, Some(exp));
+        assert!(verify_signature(KEY, PATH, Some(exp), Some(&sig)).is_ok());
+    }
+
+    #[test]
+    fn rejects_tampered_paths() {
+        let sig = sign_path(KEY, PATH, None);
+        for path in [
+            "/w:3000,f:webp/products/1.jpg",
+            "/w:300,f:webp/products/2.jpg",
+            "/w:300,f:webp/products/1.jpg/",
+        ] {
+            assert_eq!(status(verify_signature(KEY, path, None, Some(&sig))), 403);
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_or_missing_signatures() {
+        let other = sign_path("other-key", PATH, None);
+        assert_eq!(status(verify_signature(KEY, PATH, None, Some(&other))), 403);
+        assert_eq!(status(verify_signature(KEY, PATH, None, Some("%%%"))), 403);
+        assert_eq!(status(verify_signature(KEY, PATH, None, Some(""))), 403);
+        assert_eq!(status(verify_signature(KEY, PATH, None, None)), 403);
+    }
+
+    #[test]
+    fn rejects_expired_urls() {
+        let exp = unix_now() - 1;
+        let sig = sign_path(KEY, PATH, Some(exp));
+        let error = verify_signature(KEY, PATH, Some(exp), Some(&sig)).unwrap_err();
+        assert_eq!(error.status, 403);
+        assert_eq!(error.message, "URL expirada");
+    }
+
+    #[test]
+    fn rejects_altered_or_dropped_expiry() {
+        let exp = unix_now() - 1;
+        let sig = sign_path(KEY, PATH, Some(exp));
+        let extended = verify_signature(KEY, PATH, Some(exp + 3600), Some(&sig)).unwrap_err();
+        assert_eq!(extended.message, "Firma invalida");
+        let dropped = verify_signature(KEY, PATH, None, Some(&sig)).unwrap_err();
+        assert_eq!(dropped.message, "Firma invalida");
+    }
+}
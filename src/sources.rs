@@ -0,0 +1,488 @@
+use crate::config::AppConfig;
+use crate::models::HttpError;
+use percent_encoding::percent_decode_str;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use url::{Host, Url};
+
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+static REMOTE_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn http_client() -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::limited(5))
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+// Client for user supplied URLs: every hop (redirects included) is checked against
+// the host allow-list and DNS answers pointing to private ranges are dropped
+fn remote_client(policy: &RemotePolicy) -> &'static reqwest::Client {
+    REMOTE_CLIENT.get_or_init(|| build_remote_client(policy))
+}
+
+fn build_remote_client(policy: &RemotePolicy) -> reqwest::Client {
+    let redirect_policy = policy.clone();
+    let mut builder =
+        reqwest::Client::builder()
+            .no_proxy()
+            .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() > redirect_policy.max_redirects {
+                    attempt.error("Demasiadas redirecciones")
+                } else if redirect_policy.check_url(attempt.url()).is_err() {
+                    attempt.error("Redireccion a host no permitido")
+                } else {
+                    attempt.follow()
+                }
+            }));
+
+    if !policy.allow_private_networks {
+        builder = builder.dns_resolver(Arc::new(PublicResolver));
+    }
+
+    builder.build().unwrap_or_default()
+}
+
+#[derive(Clone)]
+struct RemotePolicy {
+    allowed_hosts: Vec<String>,
+    allow_private_networks: bool,
+    max_redirects: usize,
+}
+
+impl RemotePolicy {
+    fn check_url(&self, url: &Url) -> Result<(), HttpError> {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(HttpError::new(400, "Solo se permiten URLs http/https"));
+        }
+
+        let host = url
+            .host()
+            .ok_or_else(|| HttpError::new(400, "URL sin host"))?;
+        let host_name = match &host {
+            Host::Domain(domain) => domain.to_lowercase(),
+            Host::Ipv4(ip) => ip.to_string(),
+            Host::Ipv6(ip) => ip.to_string(),
+        };
+
+        if !self.allowed_hosts.is_empty()
+            && !self
+                .allowed_hosts
+                .iter()
+                .any(|allowed| host_matches(allowed, &host_name))
+        {
+            return Err(HttpError::new(403, "Host no permitido"));
+        }
+
+        let literal_ip = match host {
+            Host::Ipv4(ip) => Some(IpAddr::V4(ip)),
+            Host::Ipv6(ip) => Some(IpAddr::V6(ip)),
+            Host::Domain(_) => None,
+        };
+        if let Some(ip) = literal_ip
+            && !self.allow_private_networks
+            && !is_public_ip(ip)
+        {
+            return Err(HttpError::new(403, "Host no permitido"));
+        }
+
+        Ok(())
+    }
+}
+
+// `*.example.com` matches subdomains, anything else must match exactly
+fn host_matches(allowed: &str, host: &str) -> bool {
+    match allowed.strip_prefix("*.") {
+        Some(suffix) => host.ends_with(&format!(".{}", suffix)),
+        None => allowed == host,
+    }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)) // carrier-grade NAT
+                || (a == 192 && b == 0 && c == 0) // IETF protocol assignments
+                || (a == 198 && (b == 18 || b == 19)) // benchmarking
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            // Forms that carry an IPv4 address are judged by that address
+            if let Some(embedded) = embedded_ipv4(&ip) {
+                return is_public_ip(IpAddr::V4(embedded));
+            }
+            let segments = ip.segments();
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (segments[0] == 0x64 && segments[1] == 0xff9b) // NAT64, local-use prefixes
+                || (segments[0] & 0xfe00) == 0xfc00 // unique local
+                || (segments[0] & 0xffc0) == 0xfe80) // link local
+        }
+    }
+}
+
+fn embedded_ipv4(ip: &Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = ip.segments();
+    let from_segments = |high: u16, low: u16| {
+        let [a, b] = high.to_be_bytes();
+        let [c, d] = low.to_be_bytes();
+        Ipv4Addr::new(a, b, c, d)
+    };
+
+    if let Some(mapped) = ip.to_ipv4_mapped() {
+        return Some(mapped); // ::ffff:a.b.c.d
+    }
+    match segments {
+        // NAT64 well-known prefix 64:ff9b::/96
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => Some(from_segments(high, low)),
+        // 6to4 2002:aabb:ccdd::/48
+        [0x2002, high, low, ..] => Some(from_segments(high, low)),
+        // Deprecated IPv4-compatible ::a.b.c.d (:: and ::1 are handled by the caller)
+        [0, 0, 0, 0, 0, 0, high, low] if high != 0 || low > 1 => Some(from_segments(high, low)),
+        _ => None,
+    }
+}
+
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err("Host resuelve a una red privada".into());
+            }
+
+            let addrs: reqwest::dns::Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+pub struct FetchedSource {
+    pub bytes: Vec<u8>,
+    pub last_modified: Option<String>,
+}
+
+impl FetchedSource {
+    pub async fn read(response: reqwest::Response, max_size: usize) -> Result<Self, HttpError> {
+        let last_modified = response
+            .headers()
+            .get(reqwest::header::LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let bytes = read_body_limited(response, max_size).await?;
+
+        Ok(Self {
+            bytes,
+            last_modified,
+        })
+    }
+}
+
+pub struct SourceFetcher {
+    origin_url: Option<String>,
+    timeout: Duration,
+    max_image_size: usize,
+    remote_policy: RemotePolicy,
+}
+
+impl SourceFetcher {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            origin_url: config.sources.origin_url.clone(),
+            timeout: Duration::from_secs(config.sources.fetch_timeout_seconds),
+            max_image_size: config.compression.max_image_size,
+            remote_policy: RemotePolicy {
+                allowed_hosts: config.sources.allowed_hosts.clone(),
+                allow_private_networks: config.sources.allow_private_networks,
+                max_redirects: config.sources.max_redirects,
+            },
+        }
+    }
+
+    pub async fn fetch_from_origin(&self, source: &str) -> Result<FetchedSource, HttpError> {
+        let origin = self
+            .origin_url
+            .as_deref()
+            .ok_or_else(|| HttpError::new(404, "Origen no configurado"))?;
+
+        let origin = Url::parse(origin).map_err(|_| HttpError::new(500, "ORIGIN_URL invalida"))?;
+
+        // Segments are checked decoded, since the URL parser normalises %2e%2e and `\`
+        let source = source.trim_start_matches('/');
+        let invalid = || HttpError::new(400, "Ruta de origen invalida");
+        if source.is_empty() {
+            return Err(invalid());
+        }
+        for segment in source.split('/') {
+            let decoded = percent_decode_str(segment).decode_utf8_lossy();
+            if decoded == ".." || decoded == "." || decoded.contains(['/', '\\']) {
+                return Err(invalid());
+            }
+        }
+
+        let base_path = format!("{}/", origin.path().trim_end_matches('/'));
+        let mut base = origin.clone();
+        base.set_path(&base_path);
+        let url = base.join(source).map_err(|_| invalid())?;
+        if url.host() != origin.host()
+            || url.port_or_known_default() != origin.port_or_known_default()
+            || !url.path().starts_with(&base_path)
+        {
+            return Err(invalid());
+        }
+
+        self.download(http_client(), url.as_str()).await
+    }
+
+    pub async fn fetch_remote(&self, raw_url: &str) -> Result<FetchedSource, HttpError> {
+        let url = Url::parse(raw_url).map_err(|_| HttpError::new(400, "URL invalida"))?;
+        self.remote_policy.check_url(&url)?;
+
+        self.download(remote_client(&self.remote_policy), url.as_str())
+            .await
+    }
+
+    async fn download(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+    ) -> Result<FetchedSource, HttpError> {
+        let response = client
+            .get(url)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|_| HttpError::new(502, "Error obteniendo imagen de origen"))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(HttpError::new(404, "Imagen no encontrada en origen"));
+        }
+        if !status.is_success() {
+            return Err(HttpError::new(502, "Error obteniendo imagen de origen"));
+        }
+
+        FetchedSource::read(response, self.max_image_size).await
+    }
+}
+
+pub async fn read_body_limited(
+    mut response: reqwest::Response,
+    max_size: usize,
+) -> Result<Vec<u8>, HttpError> {
+    if response
+        .content_length()
+        .is_some_and(|len| len as usize > max_size)
+    {
+        return Err(HttpError::new(413, "Payload demasiado grande"));
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|_| HttpError::new(502, "Error obteniendo imagen de origen"))?
+    {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() > max_size {
+            return Err(HttpError::new(413, "Payload demasiado grande"));
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use std::convert::Infallible;
+
+    fn policy(allowed_hosts: &[&str], allow_private_networks: bool) -> RemotePolicy {
+        RemotePolicy {
+            allowed_hosts: allowed_hosts.iter().map(|h| h.to_string()).collect(),
+            allow_private_networks,
+            max_redirects: 2,
+        }
+    }
+
+    fn public(ip: &str) -> bool {
+        is_public_ip(ip.parse().unwrap())
+    }
+
+    // /redirect/N hops N times before answering, /to-localhost leaves the allowed host,
+    // /sized/N answers N bytes with Content-Length and /chunked/N without it
+    async fn stand_in(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let path = req.uri().path().to_string();
+        let port = req
+            .headers()
+            .get("host")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.rsplit(':').next())
+            .unwrap_or("80")
+            .to_string();
+        let redirect = |location: String| {
+            Response::builder()
+                .status(302)
+                .header("Location", location)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let size = |prefix: &str| path.strip_prefix(prefix).and_then(|n| n.parse().ok());
+
+        let response = if let Some(hops) = size("/redirect/") {
+            match hops {
+                0usize => Response::new(Body::from("ok")),
+                n => redirect(format!("/redirect/{}", n - 1)),
+            }
+        } else if path == "/to-localhost" {
+            redirect(format!("http://localhost:{}/redirect/0", port))
+        } else if let Some(n) = size("/sized/") {
+            Response::new(Body::from(vec![0u8; n]))
+        } else if let Some(n) = size("/chunked/") {
+            let chunks = (0..n).map(|_| Ok::<_, Infallible>(vec![0u8; 1]));
+            Response::new(Body::wrap_stream(futures_util::stream::iter(chunks)))
+        } else {
+            Response::builder().status(404).body(Body::empty()).unwrap()
+        };
+        Ok(response)
+    }
+
+    async fn start_stand_in() -> String {
+        let make_service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(stand_in)) });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn rejects_private_and_reserved_ipv4() {
+        for ip in [
+            "10.1.2.3",
+            "127.0.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "192.0.0.8",
+            "198.18.0.1",
+            "198.19.255.255",
+            "0.0.0.0",
+            "240.0.0.1",
+        ] {
+            assert!(!public(ip), "{}", ip);
+        }
+        assert!(public("93.184.216.34"));
+        assert!(public("198.20.0.1"));
+    }
+
+    #[test]
+    fn judges_ipv6_forms_by_their_embedded_ipv4() {
+        for ip in [
+            "::1",
+            "::",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b::10.0.0.1",
+            "64:ff9b:1::1",
+            "2002:7f00:1::",
+            "2002:c0a8:101::1",
+            "::10.0.0.1",
+            "::2",
+            "fd00::1",
+            "fe80::1",
+        ] {
+            assert!(!public(ip), "{}", ip);
+        }
+        for ip in [
+            "2606:4700::1111",
+            "::ffff:93.184.216.34",
+            "64:ff9b::5db8:d822",
+            "2002:5db8:d822::1",
+        ] {
+            assert!(public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn check_url_enforces_scheme_allow_list_and_private_literals() {
+        let open = policy(&[], false);
+        let check = |policy: &RemotePolicy, url: &str| {
+            policy
+                .check_url(&Url::parse(url).unwrap())
+                .map_err(|e| e.status)
+        };
+
+        assert_eq!(check(&open, "https://example.com/a.jpg"), Ok(()));
+        assert_eq!(check(&open, "ftp://example.com/a.jpg"), Err(400));
+        assert_eq!(check(&open, "file:///etc/passwd"), Err(400));
+        assert_eq!(check(&open, "http://127.0.0.1/a.jpg"), Err(403));
+        assert_eq!(check(&open, "http://[::ffff:10.0.0.1]/a.jpg"), Err(403));
+        assert_eq!(check(&open, "http://[64:ff9b::a9fe:a9fe]/"), Err(403));
+        assert_eq!(check(&policy(&[], true), "http://127.0.0.1/a.jpg"), Ok(()));
+
+        let listed = policy(&["cdn.example.com", "*.images.example.com"], false);
+        assert_eq!(check(&listed, "https://cdn.example.com/a.jpg"), Ok(()));
+        assert_eq!(check(&listed, "https://a.images.example.com/a.jpg"), Ok(()));
+        assert_eq!(check(&listed, "https://images.example.com/a.jpg"), Err(403));
+        assert_eq!(check(&listed, "https://evil.com/a.jpg"), Err(403));
+    }
+
+    #[tokio::test]
+    async fn follows_redirects_only_up_to_the_limit() {
+        let base = start_stand_in().await;
+        let client = build_remote_client(&policy(&[], true));
+
+        let ok = client.get(format!("{}/redirect/2", base)).send().await;
+        assert_eq!(ok.unwrap().status(), 200);
+
+        let too_many = client.get(format!("{}/redirect/3", base)).send().await;
+        assert!(too_many.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_redirects_to_hosts_outside_the_allow_list() {
+        let base = start_stand_in().await;
+        let client = build_remote_client(&policy(&["127.0.0.1"], true));
+
+        let allowed = client.get(format!("{}/redirect/1", base)).send().await;
+        assert_eq!(allowed.unwrap().status(), 200);
+
+        let escaped = client.get(format!("{}/to-localhost", base)).send().await;
+        assert!(escaped.is_err());
+    }
+
+    #[tokio::test]
+    async fn limits_body_size_with_and_without_content_length() {
+        let base = start_stand_in().await;
+        let client = build_remote_client(&policy(&[], true));
+        let read = |path: &str| {
+            let request = client.get(format!("{}{}", base, path)).send();
+            async move { read_body_limited(request.await.unwrap(), 100).await }
+        };
+
+        assert_eq!(read("/sized/100").await.unwrap().len(), 100);
+        assert_eq!(read("/sized/101").await.unwrap_err().status, 413);
+        assert_eq!(read("/chunked/100").await.unwrap().len(), 100);
+        assert_eq!(read("/chunked/101").await.unwrap_err().status, 413);
+    }
+}
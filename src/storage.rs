@@ -0,0 +1,330 @@
+use crate::config::AppConfig;
+use crate::models::HttpError;
+use crate::sources::FetchedSource;
+use crate::utils::to_hex;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+static S3_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn s3_client() -> &'static reqwest::Client {
+    S3_CLIENT.get_or_init(reqwest::Client::new)
+}
+
+pub struct S3Location {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl S3Location {
+    pub fn parse(uri: &str) -> Result<Self, HttpError> {
+        let rest = uri
+            .strip_prefix("s3://")
+            .ok_or_else(|| HttpError::new(400, "URI S3 invalida (s3://bucket/key)"))?;
+        let (bucket, key) = rest
+            .split_once('/')
+            .ok_or_else(|| HttpError::new(400, "URI S3 invalida (s3://bucket/key)"))?;
+
+        // The bucket ends up in a hostname and path-style endpoints normalise `..`,
+        // so both are restricted before the allow-list check
+        let valid_bucket = bucket
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'-');
+        if bucket.is_empty()
+            || key.is_empty()
+            || !valid_bucket
+            || key
+                .split('/')
+                .any(|segment| segment == "." || segment == "..")
+        {
+            return Err(HttpError::new(400, "URI S3 invalida (s3://bucket/key)"));
+        }
+
+        Ok(Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+
+    pub fn uri(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.key)
+    }
+
+    fn is_allowed(&self, allowed: &[String]) -> bool {
+        allowed.iter().any(|entry| match entry.split_once('/') {
+            Some((bucket, prefix)) => bucket == self.bucket && self.key.starts_with(prefix),
+            None => entry == &self.bucket,
+        })
+    }
+}
+
+// Minimal S3 client (GetObject/PutObject) signed with AWS Signature V4, so any
+// S3-compatible endpoint (MinIO, local stand-ins) works without the AWS SDK
+pub struct S3Storage {
+    endpoint: Option<String>,
+    region: String,
+    access_key_id: Option<String>,
+    secret_access_key: Option<String>,
+    session_token: Option<String>,
+    allowed_buckets: Vec<String>,
+    timeout: Duration,
+    max_image_size: usize,
+}
+
+impl S3Storage {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            endpoint: config.s3.endpoint.clone(),
+            region: config.s3.region.clone(),
+            access_key_id: config.s3.access_key_id.clone(),
+            secret_access_key: config.s3.secret_access_key.clone(),
+            session_token: config.s3.session_token.clone(),
+            allowed_buckets: config.s3.allowed_buckets.clone(),
+            timeout: Duration::from_secs(config.sources.fetch_timeout_seconds),
+            max_image_size: config.compression.max_image_size,
+        }
+    }
+
+    // For caller-supplied `source=`/`dest=` URIs; S3 event records come from AWS
+    pub fn authorized_location(&self, uri: &str) -> Result<S3Location, HttpError> {
+        let location = S3Location::parse(uri)?;
+        if !location.is_allowed(&self.allowed_buckets) {
+            return Err(HttpError::new(403, "Bucket o ruta S3 no permitida"));
+        }
+        Ok(location)
+    }
+
+    pub async fn get_object(&self, location: &S3Location) -> Result<FetchedSource, HttpError> {
+        let response = self
+            .signed_request(reqwest::Method::GET, location, Vec::new(), None)?
+            .send()
+            .await
+            .map_err(|_| HttpError::new(502, "Error accediendo a S3"))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(HttpError::new(404, "Objeto no encontrado en S3"));
+        }
+        if !status.is_success() {
+            return Err(HttpError::new(502, "Error accediendo a S3"));
+        }
+
+        FetchedSource::read(response, self.max_image_size).await
+    }
+
+    pub async fn put_object(
+        &self,
+        location: &S3Location,
+        bytes: Vec<u8>,
+        content_type: &str,
+    ) -> Result<(), HttpError> {
+        let response = self
+            .signed_request(reqwest::Method::PUT, location, bytes, Some(content_type))?
+            .send()
+            .await
+            .map_err(|_| HttpError::new(502, "Error escribiendo en S3"))?;
+
+        if !response.status().is_success() {
+            return Err(HttpError::new(502, "Error escribiendo en S3"));
+        }
+
+        Ok(())
+    }
+
+    fn object_url(&self, location: &S3Location) -> Result<Url, HttpError> {
+        if location
+            .key
+            .split('/')
+            .any(|segment| segment == "." || segment == "..")
+        {
+            return Err(HttpError::new(400, "URI S3 invalida (s3://bucket/key)"));
+        }
+        let key = encode_key(&location.key);
+        // Custom endpoints (MinIO) use path-style addressing, AWS virtual-hosted style
+        let url = match &self.endpoint {
+            Some(endpoint) => format!(
+                "{}/{}/{}",
+                endpoint.trim_end_matches('/'),
+                location.bucket,
+                key
+            ),
+            None => format!(
+                "https://{}.s3.{}.amazonaws.com/{}",
+                location.bucket, self.region, key
+            ),
+        };
+
+        Url::parse(&url).map_err(|_| HttpError::new(400, "URI S3 invalida (s3://bucket/key)"))
+    }
+
+    fn signed_request(
+        &self,
+        method: reqwest::Method,
+        location: &S3Location,
+        body: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<reqwest::RequestBuilder, HttpError> {
+        let (access_key_id, secret_access_key) = self
+            .access_key_id
+            .as_deref()
+            .zip(self.secret_access_key.as_deref())
+            .ok_or_else(|| HttpError::new(500, "Credenciales S3 no configuradas"))?;
+
+        let url = self.object_url(location)?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let (amz_date, date) = amz_timestamp(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        );
+        let payload_hash = to_hex(&Sha256::digest(&body));
+
+        let mut headers = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        if let Some(content_type) = content_type {
+            headers.push(("content-type".to_string(), content_type.to_string()));
+        }
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        headers.sort();
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\n{}",
+            method.as_str(),
+            url.path(),
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            to_hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut signing_key = hmac_sha256(
+            format!("AWS4{}", secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes());
+        }
+        let signature = to_hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            access_key_id, scope, signed_headers, signature
+        );
+
+        let mut request = s3_client()
+            .request(method, url)
+            .timeout(self.timeout)
+            .header("Authorization", authorization);
+        for (name, value) in headers.into_iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+
+        Ok(request.body(body))
+    }
+}
+
+// RFC 3986 encoding for each key segment, keeping `/` separators
+fn encode_key(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC acepta cualquier clave");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// Returns (`YYYYMMDDTHHMMSSZ`, `YYYYMMDD`) for a Unix timestamp
+fn amz_timestamp(secs: u64) -> (String, String) {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil-from-days (Howard Hinnant)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let time = format!("{:02}{:02}{:02}", rem / 3600, (rem % 3600) / 60, rem % 60);
+
+    (format!("{}T{}Z", date, time), date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(uri: &str, list: &[&str]) -> bool {
+        let list: Vec<String> = list.iter().map(|s| s.to_string()).collect();
+        S3Location::parse(uri).unwrap().is_allowed(&list)
+    }
+
+    #[test]
+    fn allow_list_matches_buckets_and_prefixes() {
+        assert!(allowed("s3://images/a.jpg", &["images"]));
+        assert!(allowed(
+            "s3://uploads/tmp/a.jpg",
+            &["images", "uploads/tmp/"]
+        ));
+        assert!(!allowed("s3://uploads/private/a.jpg", &["uploads/tmp/"]));
+        assert!(!allowed("s3://images-backup/a.jpg", &["images"]));
+        assert!(!allowed("s3://images/a.jpg", &[]));
+    }
+
+    #[test]
+    fn parse_rejects_dot_segments_and_host_characters() {
+        for uri in [
+            "s3://images/tmp/../secret.jpg",
+            "s3://images/./a.jpg",
+            "s3://evil.com#/a.jpg",
+            "s3://Images/a.jpg",
+            "s3://images/",
+            "https://images/a.jpg",
+        ] {
+            assert!(S3Location::parse(uri).is_err(), "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::models::{ResizeMode, ResizeOptions};
+use crate::text::FONT_BYTES;
+use image::{DynamicImage, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use std::sync::{Arc, OnceLock};
+
+static FONT_DB: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+
+// Guards against tiny SVGs asking for huge rasters (e.g. w=100000)
+const MAX_RASTER_SIDE: f32 = 8192.0;
+
+pub fn is_svg(bytes: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<svg") || head.starts_with("<!--"))
+        && head.contains("<svg")
+}
+
+// Only the bundled font, and no file or URL references: `href`s other than data: URIs are dropped
+pub fn load_svg(bytes: &[u8]) -> Result<usvg::Tree, String> {
+    let fontdb = FONT_DB
+        .get_or_init(|| {
+            let mut db = usvg::fontdb::Database::new();
+            db.load_font_data(FONT_BYTES.to_vec());
+            Arc::new(db)
+        })
+        .clone();
+
+    let options = usvg::Options {
+        resources_dir: None,
+        font_family: "DejaVu Sans".to_string(),
+        image_href_resolver: usvg::ImageHrefResolver {
+            resolve_data: usvg::ImageHrefResolver::default_data_resolver(),
+            resolve_string: Box::new(|_, _| None),
+        },
+        fontdb,
+        ..usvg::Options::default()
+    };
+
+    usvg::Tree::from_data(bytes, &options).map_err(|_| "SVG invalido".to_string())
+}
+
+// Scale that renders the SVG at the requested size, so the resize step does not upscale a small raster
+pub fn svg_scale(tree: &usvg::Tree, resize: Option<&ResizeOptions>, dpr: f32) -> f32 {
+    let size = tree.size();
+    let (width, height) = (size.width(), size.height());
+
+    let Some(resize) = resize else {
+        return dpr;
+    };
+    match (resize.width, resize.height) {
+        (Some(w), Some(h)) => {
+            let (sx, sy) = (w as f32 / width, h as f32 / height);
+            match resize.mode {
+                ResizeMode::Fit => sx.min(sy),
+                ResizeMode::Fill | ResizeMode::Force => sx.max(sy),
+            }
+        }
+        (Some(w), None) => w as f32 / width,
+        (None, Some(h)) => h as f32 / height,
+        (None, None) => dpr,
+    }
+}
+
+pub fn render_svg(tree: &usvg::Tree, scale: f32) -> Result<DynamicImage, String> {
+    let size = tree.size();
+    let width = (size.width() * scale).round();
+    let height = (size.height() * scale).round();
+    if width > MAX_RASTER_SIDE || height > MAX_RASTER_SIDE {
+        return Err("SVG demasiado grande para rasterizar".to_string());
+    }
+
+    let mut pixmap = tiny_skia::Pixmap::new((width as u32).max(1), (height as u32).max(1))
+        .ok_or_else(|| "Dimensiones invalidas".to_string())?;
+    resvg::render(
+        tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    // tiny-skia stores premultiplied alpha
+    let mut rgba = RgbaImage::from_raw(pixmap.width(), pixmap.height(), pixmap.take())
+        .ok_or_else(|| "Error rasterizando SVG".to_string())?;
+    for pixel in rgba.pixels_mut() {
+        let alpha = pixel.0[3];
+        if alpha > 0 && alpha < 255 {
+            for c in 0..3 {
+                pixel.0[c] = ((pixel.0[c] as u32 * 255 + alpha as u32 / 2) / alpha as u32) as u8;
+            }
+        }
+    }
+
+    Ok(DynamicImage::ImageRgba8(rgba))
+}
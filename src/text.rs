@@ -0,0 +1,332 @@
+use crate::models::{Gravity, TextOverlay};
+use ab_glyph::{Font, FontRef, GlyphId, PxScale, ScaleFont, point};
+use image::{Rgba, RgbaImage};
+
+// DejaVu Sans Bold, see assets/fonts/LICENSE-DejaVu.txt
+pub static FONT_BYTES: &[u8] = include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf");
+
+enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+pub fn draw_text_overlay(img: &mut RgbaImage, overlay: &TextOverlay) -> Result<(), String> {
+    let font =
+        FontRef::try_from_slice(FONT_BYTES).map_err(|_| "Error cargando fuente".to_string())?;
+    let scaled = font.as_scaled(PxScale::from(overlay.size));
+
+    let (img_w, img_h) = img.dimensions();
+    let margin = (overlay.size / 2.0).round() as i64;
+    let max_width = overlay
+        .max_width
+        .map(|w| w.min(img_w) as f32)
+        .unwrap_or_else(|| (img_w as i64 - 2 * margin).max(1) as f32);
+
+    let lines = wrap_lines(&scaled, &overlay.text, max_width);
+    if lines.iter().all(|line| line.is_empty()) {
+        return Ok(());
+    }
+
+    let line_height = scaled.height() + scaled.line_gap();
+    let widths: Vec<f32> = lines.iter().map(|line| line_width(&scaled, line)).collect();
+    let block_w = widths.iter().cloned().fold(0.0, f32::max).ceil() as i64;
+    let block_h = (line_height * lines.len() as f32).ceil() as i64;
+
+    let pad = overlay.stroke_width as i64;
+    let (origin_x, origin_y) = anchor(
+        overlay.gravity,
+        (img_w as i64, img_h as i64),
+        (block_w + 2 * pad, block_h + 2 * pad),
+        margin - pad,
+    );
+
+    // Only the part of the text block that can reach the image is rasterised: the
+    // stroke and the shadow pull in pixels up to `reach` outside the image edges
+    let reach = pad + overlay.shadow_offset.unsigned_abs() as i64;
+    let x0 = (-reach - origin_x).max(0);
+    let y0 = (-reach - origin_y).max(0);
+    let x1 = (img_w as i64 + reach - origin_x).min(block_w + 2 * pad);
+    let y1 = (img_h as i64 + reach - origin_y).min(block_h + 2 * pad);
+    if x1 <= x0 || y1 <= y0 {
+        return Ok(());
+    }
+    let (mask_w, mask_h) = ((x1 - x0) as u32, (y1 - y0) as u32);
+    let mut fill = vec![0.0f32; mask_w as usize * mask_h as usize];
+
+    let alignment = match overlay.gravity {
+        Gravity::West | Gravity::NorthWest | Gravity::SouthWest => Alignment::Left,
+        Gravity::East | Gravity::NorthEast | Gravity::SouthEast => Alignment::Right,
+        _ => Alignment::Center,
+    };
+
+    for (index, (line, width)) in lines.iter().zip(&widths).enumerate() {
+        let x_offset = match alignment {
+            Alignment::Left => 0.0,
+            Alignment::Center => (block_w as f32 - width) / 2.0,
+            Alignment::Right => block_w as f32 - width,
+        };
+        let mut caret = point(
+            (pad - x0) as f32 + x_offset,
+            (pad - y0) as f32 + index as f32 * line_height + scaled.ascent(),
+        );
+        let mut previous: Option<GlyphId> = None;
+
+        for c in line.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(prev) = previous {
+                caret.x += scaled.kern(prev, id);
+            }
+            let glyph = id.with_scale_and_position(scaled.scale(), caret);
+            caret.x += scaled.h_advance(id);
+            previous = Some(id);
+
+            if let Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                if bounds.max.x < 0.0
+                    || bounds.max.y < 0.0
+                    || bounds.min.x > mask_w as f32
+                    || bounds.min.y > mask_h as f32
+                {
+                    continue;
+                }
+                outlined.draw(|gx, gy, coverage| {
+                    let x = bounds.min.x as i64 + gx as i64;
+                    let y = bounds.min.y as i64 + gy as i64;
+                    if x >= 0 && y >= 0 && x < mask_w as i64 && y < mask_h as i64 {
+                        let idx = y as usize * mask_w as usize + x as usize;
+                        fill[idx] = (fill[idx] + coverage).min(1.0);
+                    }
+                });
+            }
+        }
+    }
+
+    let outline = if overlay.stroke_width > 0 {
+        dilate(&fill, mask_w, mask_h, overlay.stroke_width)
+    } else {
+        fill.clone()
+    };
+    let (origin_x, origin_y) = (origin_x + x0, origin_y + y0);
+
+    if overlay.shadow_offset != 0 {
+        let offset = overlay.shadow_offset as i64;
+        blend_mask(
+            img,
+            &outline,
+            (mask_w, mask_h),
+            (origin_x + offset, origin_y + offset),
+            overlay.shadow_color,
+        );
+    }
+
+    if overlay.stroke_width > 0 {
+        blend_mask(
+            img,
+            &outline,
+            (mask_w, mask_h),
+            (origin_x, origin_y),
+            overlay.stroke_color,
+        );
+    }
+
+    blend_mask(
+        img,
+        &fill,
+        (mask_w, mask_h),
+        (origin_x, origin_y),
+        overlay.color,
+    );
+
+    Ok(())
+}
+
+fn wrap_lines<F: Font, SF: ScaleFont<F>>(font: &SF, text: &str, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut current = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if current.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", current, word)
+            };
+
+            if !current.is_empty() && line_width(font, &candidate) > max_width {
+                lines.push(std::mem::replace(&mut current, word.to_string()));
+            } else {
+                current = candidate;
+            }
+        }
+        lines.push(current);
+    }
+
+    lines
+}
+
+fn line_width<F: Font, SF: ScaleFont<F>>(font: &SF, line: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous: Option<GlyphId> = None;
+
+    for c in line.chars() {
+        let id = font.glyph_id(c);
+        if let Some(prev) = previous {
+            width += font.kern(prev, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+
+    width
+}
+
+// Exact Euclidean distance transform (Felzenszwalb-Huttenlocher), so the cost does not
+// grow with the stroke radius; the stroke edge is anti-aliased over one pixel
+fn dilate(mask: &[f32], width: u32, height: u32, radius: u32) -> Vec<f32> {
+    let (w, h) = (width as usize, height as usize);
+    // Larger than any squared distance inside the mask, yet small enough to stay exact
+    let far = ((w + h) as f64).powi(2);
+    let mut distance: Vec<f64> = mask
+        .iter()
+        .map(|c| if *c >= 0.5 { 0.0 } else { far })
+        .collect();
+
+    let longest = w.max(h);
+    let mut line = vec![0.0f64; longest];
+    let mut out_line = vec![0.0f64; longest];
+    let mut parabolas = vec![0usize; longest];
+    let mut bounds = vec![0.0f64; longest + 1];
+
+    for x in 0..w {
+        for y in 0..h {
+            line[y] = distance[y * w + x];
+        }
+        squared_distance_1d(&line[..h], &mut out_line, &mut parabolas, &mut bounds);
+        for y in 0..h {
+            distance[y * w + x] = out_line[y];
+        }
+    }
+    for y in 0..h {
+        line[..w].copy_from_slice(&distance[y * w..(y + 1) * w]);
+        squared_distance_1d(&line[..w], &mut out_line, &mut parabolas, &mut bounds);
+        distance[y * w..(y + 1) * w].copy_from_slice(&out_line[..w]);
+    }
+
+    let edge = radius as f64 + 0.5;
+    mask.iter()
+        .zip(&distance)
+        .map(|(coverage, d)| coverage.max((edge - d.sqrt()).clamp(0.0, 1.0) as f32))
+        .collect()
+}
+
+// Lower envelope of the parabolas (q - p)^2 + f(p); writes the squared distance of every sample
+fn squared_distance_1d(f: &[f64], out: &mut [f64], v: &mut [usize], z: &mut [f64]) {
+    let n = f.len();
+    if n == 0 {
+        return;
+    }
+    let intersect = |q: usize, p: usize| {
+        let (qf, pf) = (q as f64, p as f64);
+        ((f[q] + qf * qf) - (f[p] + pf * pf)) / (2.0 * qf - 2.0 * pf)
+    };
+
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+    for q in 1..n {
+        let mut s = intersect(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersect(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f64::INFINITY;
+    }
+
+    k = 0;
+    for (q, value) in out.iter_mut().enumerate().take(n) {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let d = q as f64 - v[k] as f64;
+        *value = d * d + f[v[k]];
+    }
+}
+
+fn anchor(gravity: Gravity, image: (i64, i64), block: (i64, i64), margin: i64) -> (i64, i64) {
+    let (img_w, img_h) = image;
+    let (block_w, block_h) = block;
+
+    let left = margin;
+    let center_x = (img_w - block_w) / 2;
+    let right = img_w - block_w - margin;
+    let top = margin;
+    let center_y = (img_h - block_h) / 2;
+    let bottom = img_h - block_h - margin;
+
+    match gravity {
+        Gravity::Center => (center_x, center_y),
+        Gravity::North => (center_x, top),
+        Gravity::South => (center_x, bottom),
+        Gravity::East => (right, center_y),
+        Gravity::West => (left, center_y),
+        Gravity::NorthEast => (right, top),
+        Gravity::NorthWest => (left, top),
+        Gravity::SouthEast => (right, bottom),
+        Gravity::SouthWest => (left, bottom),
+    }
+}
+
+fn blend_mask(
+    img: &mut RgbaImage,
+    mask: &[f32],
+    mask_size: (u32, u32),
+    origin: (i64, i64),
+    color: [u8; 4],
+) {
+    let (width, height) = img.dimensions();
+    let (mask_w, mask_h) = mask_size;
+
+    for my in 0..mask_h {
+        let y = origin.1 + my as i64;
+        if y < 0 || y >= height as i64 {
+            continue;
+        }
+        for mx in 0..mask_w {
+            let x = origin.0 + mx as i64;
+            if x < 0 || x >= width as i64 {
+                continue;
+            }
+
+            let coverage = mask[(my * mask_w + mx) as usize];
+            if coverage <= 0.0 {
+                continue;
+            }
+
+            let alpha = coverage * color[3] as f32 / 255.0;
+            let pixel = img.get_pixel_mut(x as u32, y as u32);
+            *pixel = blend_pixel(*pixel, color, alpha);
+        }
+    }
+}
+
+fn blend_pixel(dst: Rgba<u8>, color: [u8; 4], alpha: f32) -> Rgba<u8> {
+    let dst_a = dst.0[3] as f32 / 255.0;
+    let out_a = alpha + dst_a * (1.0 - alpha);
+    if out_a <= 0.0 {
+        return dst;
+    }
+
+    let mut out = [0u8; 4];
+    for c in 0..3 {
+        let value = (color[c] as f32 * alpha + dst.0[c] as f32 * dst_a * (1.0 - alpha)) / out_a;
+        out[c] = value.round().clamp(0.0, 255.0) as u8;
+    }
+    out[3] = (out_a * 255.0).round() as u8;
+
+    Rgba(out)
+}
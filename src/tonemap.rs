@@ -0,0 +1,67 @@
+use crate::models::ToneMap;
+use crate::utils::{linear_to_srgb, srgb_to_linear};
+use image::{ColorType, DynamicImage, Rgba};
+
+pub fn parse_tone_map(value: &str) -> Result<ToneMap, String> {
+    match value.to_lowercase().as_str() {
+        "none" | "clip" => Ok(ToneMap::None),
+        "reinhard" => Ok(ToneMap::Reinhard),
+        "aces" => Ok(ToneMap::Aces),
+        _ => Err(format!("Tone mapping invalido: {}", value)),
+    }
+}
+
+pub fn is_float(img: &DynamicImage) -> bool {
+    matches!(img.color(), ColorType::Rgb32F | ColorType::Rgba32F)
+}
+
+pub fn is_high_precision(img: &DynamicImage) -> bool {
+    is_float(img)
+        || matches!(
+            img.color(),
+            ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16
+        )
+}
+
+// Float (HDR/EXR) pixels are scene-linear, 16-bit ones are sRGB-encoded; both are
+// exposed, compressed into 0-1 and re-encoded to sRGB
+pub fn tone_map(
+    img: &DynamicImage,
+    operator: ToneMap,
+    exposure: f32,
+    keep_16bit: bool,
+) -> DynamicImage {
+    let linear_input = is_float(img);
+    let has_alpha = img.color().has_alpha();
+    let gain = 2f32.powf(exposure);
+
+    let mut rgba = img.to_rgba32f();
+    for pixel in rgba.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let map = |c: f32| {
+            let linear = if linear_input { c } else { srgb_to_linear(c) };
+            linear_to_srgb(tone_curve(operator, linear.max(0.0) * gain))
+        };
+        *pixel = Rgba([map(r), map(g), map(b), a.clamp(0.0, 1.0)]);
+    }
+
+    let rgba = DynamicImage::ImageRgba32F(rgba);
+    match (keep_16bit, has_alpha) {
+        (true, true) => DynamicImage::ImageRgba16(rgba.to_rgba16()),
+        (true, false) => DynamicImage::ImageRgb16(rgba.to_rgb16()),
+        (false, true) => DynamicImage::ImageRgba8(rgba.to_rgba8()),
+        (false, false) => DynamicImage::ImageRgb8(rgba.to_rgb8()),
+    }
+}
+
+fn tone_curve(operator: ToneMap, c: f32) -> f32 {
+    match operator {
+        ToneMap::None => c.min(1.0),
+        ToneMap::Reinhard => c / (1.0 + c),
+        // Narkowicz's fit of the ACES filmic curve
+        ToneMap::Aces => {
+            let x = c * 0.6;
+            ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+        }
+    }
+}
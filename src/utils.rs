@@ -1,7 +1,106 @@
 use crate::models::ImageData;
 use base64::{Engine as _, engine::general_purpose};
+use std::io::{Cursor, Write};
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
 
 pub fn decode_base64(base64_data: &str) -> Result<ImageData, Box<dyn std::error::Error>> {
     let bytes = general_purpose::STANDARD.decode(base64_data)?;
     Ok(ImageData { bytes })
 }
+
+pub fn parse_hex_color(raw: &str) -> Option<[u8; 4]> {
+    let hex = raw.trim().trim_start_matches('#');
+    if !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+    match hex.len() {
+        6 => Some([channel(0)?, channel(2)?, channel(4)?, 255]),
+        8 => Some([channel(0)?, channel(2)?, channel(4)?, channel(6)?]),
+        _ => None,
+    }
+}
+
+// "#rrggbb" from the first three channels; alpha is dropped
+pub fn css_hex(color: &[u8]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// Client filenames end up in ZIP entries and Content-Disposition, so only the basename
+// is kept and reduced to [A-Za-z0-9._-]; None when nothing usable is left
+pub fn safe_filename(name: &str) -> Option<String> {
+    let basename = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = basename
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .collect();
+    let cleaned = cleaned.trim_start_matches('.');
+    (!cleaned.is_empty()).then(|| cleaned.to_string())
+}
+
+// For caller-supplied text placed inside HTML attributes
+pub fn html_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// High-density screens hide compression artifacts, so higher DPRs get a lower default quality
+pub fn quality_for_dpr(quality: u8, dpr: f32) -> u8 {
+    if dpr <= 1.0 {
+        return quality;
+    }
+    ((quality as f32 / dpr.powf(0.4)).round() as u8).max(1)
+}
+
+// Images are already compressed, so entries are stored as-is
+pub fn build_zip(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>, String> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    for (name, bytes) in entries {
+        writer
+            .start_file(name.as_str(), options)
+            .map_err(|_| "Error creando ZIP".to_string())?;
+        writer
+            .write_all(bytes)
+            .map_err(|_| "Error creando ZIP".to_string())?;
+    }
+
+    let cursor = writer
+        .finish()
+        .map_err(|_| "Error creando ZIP".to_string())?;
+    Ok(cursor.into_inner())
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}